@@ -1,8 +1,16 @@
 /// de
-use crate::{parse, Key};
+use crate::{parse, Document, Group, Key, Sections};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    error, fmt,
+    num::{ParseFloatError, ParseIntError},
+};
 use nom::Finish;
-use serde::de::{self, IntoDeserializer, SeqAccess};
-use std::{error, fmt, num::ParseIntError};
+use serde::de::{self, value::BorrowedStrDeserializer, IntoDeserializer, SeqAccess};
 
 #[derive(Debug)]
 pub enum Error {
@@ -10,19 +18,111 @@ pub enum Error {
     Message(String),
     TrailingCharacters,
     ExpectAssignment(parse::de::OwnedError),
-    ExpectBool(parse::de::OwnedKey),
+    ExpectBool {
+        found: parse::de::OwnedKey,
+        accepted: Vec<String>,
+    },
     ExpectChar(parse::de::OwnedKey),
     ExpectNum(ParseIntError),
+    ExpectFloat(ParseFloatError),
     ExpectIdent(String),
     Unsupported(&'static str),
+    Unknown(Vec<Unknown>),
+    MissingSection(String),
+    /// A `[section]` repeated after other sections, rather than right after its previous body
+    NotAdjacent(String),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// An error at a path of the target type, and the line of the input when known
+    At {
+        path: Path,
+        line: Option<usize>,
+        error: Box<Error>,
+    },
 }
 
-impl<'a> error::Error for Error {}
+impl Error {
+    /// The path of the value which failed to deserialize (IE: `general.assets[3]`)
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::At { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The line of the value which failed to deserialize
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Error::At { line, .. } => *line,
+            _ => None,
+        }
+    }
+
+    /// The error without its location
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::At { error, .. } => error,
+            error => error,
+        }
+    }
+
+    // Locate an error within a parent, IE: the section of a sub document
+    fn within(self, parent: Segment) -> Error {
+        match self {
+            Error::At {
+                mut path,
+                line,
+                error,
+            } => {
+                path.0.insert(0, parent);
+                Error::At { path, line, error }
+            }
+            error => error.at(&[parent], None),
+        }
+    }
+
+    // Replace the line of the error, and of any unknown keys
+    fn relocate<F: Fn(Option<usize>) -> Option<usize>>(self, f: F) -> Error {
+        match self {
+            Error::At { path, line, error } => Error::At {
+                path,
+                line: f(line),
+                error,
+            },
+            Error::Unknown(unknown) => {
+                Error::Unknown(unknown.into_iter().map(|u| u.relocate(&f)).collect())
+            }
+            error => error,
+        }
+    }
+
+    // Locate an error at the innermost path, errors are only located once
+    fn at(self, segments: &[Segment], line: Option<usize>) -> Error {
+        match self {
+            Error::At { .. } => self,
+            error if segments.is_empty() => error,
+            error => Error::At {
+                path: Path(segments.to_vec()),
+                line,
+                error: Box::new(error),
+            },
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::At { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl de::Error for Error {
     fn custom<T>(msg: T) -> Self
     where
-        T: std::fmt::Display,
+        T: fmt::Display,
     {
         Error::Message(msg.to_string())
     }
@@ -34,12 +134,25 @@ impl<'a> From<parse::Error<'a>> for Error {
     }
 }
 
-impl<'a> From<ParseIntError> for Error {
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<ParseIntError> for Error {
     fn from(value: ParseIntError) -> Self {
         Error::ExpectNum(value)
     }
 }
 
+impl From<ParseFloatError> for Error {
+    fn from(value: ParseFloatError) -> Self {
+        Error::ExpectFloat(value)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -47,43 +160,496 @@ impl fmt::Display for Error {
             Error::TrailingCharacters => write!(fmt, "junk at end of input"),
             Error::Parser(e) => e.fmt(fmt),
             Error::ExpectAssignment(e) => write!(fmt, "expected assignment, found {e}"),
-            Error::ExpectBool(key) => write!(fmt, "expected bool, found {:?}", key),
+            Error::ExpectBool { found, accepted } => write!(
+                fmt,
+                "expected bool (one of {}), found {:?}",
+                accepted.join(", "),
+                found
+            ),
             Error::ExpectChar(key) => write!(fmt, "expected char, found {:?}", key),
             Error::ExpectNum(key) => write!(fmt, "expected number, found {:?}", key),
-            Error::ExpectIdent(key) => write!(fmt, "expected number, found {:?}", key),
+            Error::ExpectFloat(key) => write!(fmt, "expected float, found {:?}", key),
+            Error::ExpectIdent(key) => write!(fmt, "expected key or [section], found {:?}", key),
             Error::Unsupported(s) => write!(fmt, "{s} is not unsupported"),
+            Error::Unknown(unknown) => {
+                write!(fmt, "unknown ")?;
+                for (i, u) in unknown.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, ", ")?;
+                    }
+                    u.fmt(fmt)?;
+                }
+                Ok(())
+            }
+            Error::MissingSection(name) => write!(fmt, "missing [{name}] section"),
+            Error::NotAdjacent(name) => write!(
+                fmt,
+                "[{name}] is repeated after other sections, repeated sections must be adjacent"
+            ),
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(fmt, "read error {e}"),
+            Error::At {
+                path,
+                line: Some(line),
+                error,
+            } => write!(fmt, "{path} at line {line}: {error}"),
+            Error::At {
+                path,
+                line: None,
+                error,
+            } => write!(fmt, "{path}: {error}"),
+        }
+    }
+}
+
+/// A segment of a [`Path`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// A key, or the name of a `[section]`
+    Key(String),
+    /// An element of an array, or one of repeated `[section]`s
+    Index(usize),
+}
+
+impl From<&Key<'_>> for Segment {
+    fn from(value: &Key<'_>) -> Self {
+        match *value {
+            Key::Num(n) => Segment::Key(n.to_string()),
+            Key::Str(s) => Segment::Key(s.to_string()),
+        }
+    }
+}
+
+/// Where a value is in the target type, displayed as `general.assets[3]`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(Vec<Segment>);
+
+impl Path {
+    pub fn segments(&self) -> &[Segment] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(fmt, "{key}")?,
+                Segment::Key(key) => write!(fmt, ".{key}")?,
+                Segment::Index(index) => write!(fmt, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A key or section of the input which was not consumed by the target type
+///
+/// From a [`Document`], the line is of the text it was parsed from, see [`Document::lines`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unknown {
+    /// An ignored `[section]` and its body
+    Section { name: String, line: Option<usize> },
+    /// An ignored `key = value` pair, and the `[section]` it belongs to
+    Key {
+        section: Option<String>,
+        key: String,
+        line: Option<usize>,
+    },
+}
+
+impl Unknown {
+    fn relocate<F: Fn(Option<usize>) -> Option<usize>>(self, f: F) -> Unknown {
+        match self {
+            Unknown::Section { name, line } => Unknown::Section {
+                name,
+                line: f(line),
+            },
+            Unknown::Key { section, key, line } => Unknown::Key {
+                section,
+                key,
+                line: f(line),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Unknown {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let line = match self {
+            Unknown::Section { name, line } => {
+                write!(fmt, "[{name}]")?;
+                line
+            }
+            Unknown::Key {
+                section: Some(section),
+                key,
+                line,
+            } => {
+                write!(fmt, "{section}.{key}")?;
+                line
+            }
+            Unknown::Key {
+                section: None,
+                key,
+                line,
+            } => {
+                write!(fmt, "{key}")?;
+                line
+            }
+        };
+        match line {
+            Some(line) => write!(fmt, " at line {line}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Options to tune how values are interpreted by the [`Deserializer`]
+#[derive(Debug, Clone)]
+pub struct Options {
+    // Values which deserialize to `None` (case insensitive), in addition to empty values
+    none: Vec<String>,
+    // Values which deserialize to `true` (case insensitive)
+    truthy: Vec<String>,
+    // Values which deserialize to `false` (case insensitive)
+    falsy: Vec<String>,
+    // Fail when the input has keys or sections which the target type ignored
+    strict: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            none: Vec::new(),
+            truthy: ["true", "yes", "on", "y", "1"].map(String::from).to_vec(),
+            falsy: ["false", "no", "off", "n", "0"].map(String::from).to_vec(),
+            strict: false,
+        }
+    }
+}
+
+impl Options {
+    /// Replace the default boolean vocabulary (true/yes/on/y/1 and false/no/off/n/0)
+    pub fn bool_values<T, F>(mut self, truthy: T, falsy: F) -> Self
+    where
+        T: IntoIterator,
+        T::Item: Into<String>,
+        F: IntoIterator,
+        F::Item: Into<String>,
+    {
+        self.truthy = truthy.into_iter().map(Into::into).collect();
+        self.falsy = falsy.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Treat these values as `None` when deserializing an `Option` (IE: `none`, `null`)
+    pub fn none_values<I, S>(mut self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.none = values.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Return [`Error::Unknown`] when the target type ignored any keys or sections
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Deserialize an instance of type `T` from a string of ini text using these options
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_str<'a, T>(&self, input: &'a str) -> Result<T, Error>
+    where
+        T: de::Deserialize<'a>,
+    {
+        self.from_str_with_unknown(input).map(|(t, _)| t)
+    }
+
+    /// Deserialize an instance of type `T`, also returning the keys and sections it ignored
+    ///
+    /// NOTE keys collected by a `#[serde(flatten)]` map are consumed by that map
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_str_with_unknown<'a, T>(&self, input: &'a str) -> Result<(T, Vec<Unknown>), Error>
+    where
+        T: de::Deserialize<'a>,
+    {
+        let mut deserializer = Deserializer::with_options(input, self.clone());
+        let t = T::deserialize(&mut deserializer)?;
+        if !deserializer.is_finished() {
+            return Err(Error::TrailingCharacters);
+        }
+        self.check_unknown(t, deserializer.unknown)
+    }
+
+    /// Deserialize an instance of type `T` from a parsed [`Document`] using these options
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_document<T>(&self, document: &Document<'_>) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        self.from_document_with_unknown(document).map(|(t, _)| t)
+    }
+
+    /// Deserialize an instance of type `T` from a parsed [`Document`], also returning the keys and
+    /// sections it ignored. The document is written back as ini text, and the lines of that text
+    /// are reported as the lines the document was parsed from
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_document_with_unknown<T>(
+        &self,
+        document: &Document<'_>,
+    ) -> Result<(T, Vec<Unknown>), Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let (text, lines): (Vec<String>, Vec<Option<usize>>) = document.lines().into_iter().unzip();
+        let mut text = text.join("\n");
+        text.push('\n');
+        let relocate = |line: Option<usize>| line.and_then(|line| *lines.get(line - 1)?);
+        match self.from_str_with_unknown(&text) {
+            Ok((t, unknown)) => Ok((
+                t,
+                unknown.into_iter().map(|u| u.relocate(relocate)).collect(),
+            )),
+            Err(error) => Err(error.relocate(relocate)),
+        }
+    }
+
+    /// Deserialize a single `[section]` of ini text, with its `[section.subsection]`s as nested
+    /// sections (IE: `[general.fees]` is the `fees` section of `[general]`)
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_section<T>(&self, input: &str, name: &str) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let document = crate::parse_document(input)?;
+        self.from_document_section(&document, name)
+    }
+
+    /// Deserialize a single `[section]` of a parsed [`Document`], see [`Options::from_section`]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_document_section<T>(&self, document: &Document<'_>, name: &str) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let groups = document
+            .sections
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .map(|(_, group)| group);
+        let sections = document.sections.iter().map(|(n, group)| (*n, group));
+        self.deserialize_subdocument(document, groups, sections, name)
+    }
+
+    /// Deserialize a single `[section]` of parsed [`Sections`], see [`Options::from_section`]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_sections<T>(&self, sections: &Sections<'_>, name: &str) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+    {
+        let subsections = sections.iter().map(|(n, group)| (*n, group));
+        let document = Document::default();
+        self.deserialize_subdocument(&document, sections.get(name), subsections, name)
+    }
+
+    // Deserialize the groups of a section as the root of a document whose sections are its
+    // subsections, located in the text of the parent document
+    fn deserialize_subdocument<'s, 'a, T, G, S>(
+        &self,
+        parent: &Document<'a>,
+        groups: G,
+        sections: S,
+        name: &str,
+    ) -> Result<T, Error>
+    where
+        T: de::DeserializeOwned,
+        G: IntoIterator<Item = &'s Group<'a>>,
+        S: IntoIterator<Item = (&'a str, &'s Group<'a>)>,
+        'a: 's,
+    {
+        let groups: Vec<&Group<'a>> = groups.into_iter().collect();
+        let root = match groups.as_slice() {
+            [] => return Err(Error::MissingSection(name.to_string())),
+            [group] => (*group).clone(),
+            groups => {
+                return Err(de::Error::custom(format_args!(
+                    "expected a single [{name}] section, found {}",
+                    groups.len()
+                )))
+            }
+        };
+        let prefix = alloc::format!("{name}.");
+        let sections = sections
+            .into_iter()
+            .filter_map(|(n, group)| Some((n.strip_prefix(prefix.as_str())?, group.clone())))
+            .collect();
+        self.from_document(&parent.part(root, sections))
+            .map_err(|e| e.within(Segment::Key(name.to_string())))
+    }
+
+    fn check_unknown<T>(&self, t: T, unknown: Vec<Unknown>) -> Result<(T, Vec<Unknown>), Error> {
+        match self.strict && !unknown.is_empty() {
+            true => Err(Error::Unknown(unknown)),
+            false => Ok((t, unknown)),
+        }
+    }
+
+    fn is_none(&self, value: &str) -> bool {
+        self.none
+            .iter()
+            .any(|none| none.eq_ignore_ascii_case(value))
+    }
+
+    fn to_bool(&self, value: &str) -> Option<bool> {
+        if self.truthy.iter().any(|t| t.eq_ignore_ascii_case(value)) {
+            Some(true)
+        } else if self.falsy.iter().any(|f| f.eq_ignore_ascii_case(value)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn expect_bool(&self, found: Key) -> Error {
+        Error::ExpectBool {
+            found: found.into(),
+            accepted: self
+                .truthy
+                .iter()
+                .chain(self.falsy.iter())
+                .cloned()
+                .collect(),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Deserializer<'de> {
+    // The complete input, for reporting line numbers
+    source: &'de str,
     // Remaining input string left to be parsed
     input: &'de str,
     // A cached identifier for validating subsection names
     ident: Option<&'de str>,
+    // A [section] header which was just parsed and whose body was not yet deserialized
+    header: Option<&'de str>,
+    // The pending [section] header is one element of repeated sections, not the whole sequence
+    element: bool,
+    // Nothing was deserialized yet, so the input is the root map of the document
+    root: bool,
+    // Inside a comma separated array, whose elements are scalars
+    array: bool,
+    // Deserializing the key of a `key = value` pair rather than its value
+    in_key: bool,
+    // The root [section]s deserialized so far
+    sections: Vec<&'de str>,
+    // The path (and the line of each segment) of the value being deserialized
+    path: Vec<(Segment, usize)>,
+    // The key (and its line) whose value is being deserialized
+    key: Option<(parse::de::Ident<'de>, usize)>,
+    // Keys and sections which the target type ignored
+    unknown: Vec<Unknown>,
+    // Caller provided options
+    options: Options,
 }
 
 impl<'de> Deserializer<'de> {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(input: &'de str) -> Self {
-        Self { input, ident: None }
+        Self::with_options(input, Options::default())
+    }
+
+    pub fn with_options(input: &'de str, options: Options) -> Self {
+        Self {
+            source: input,
+            input,
+            ident: None,
+            header: None,
+            element: false,
+            root: true,
+            array: false,
+            in_key: false,
+            sections: Vec::new(),
+            path: Vec::new(),
+            key: None,
+            unknown: Vec::new(),
+            options,
+        }
+    }
+
+    /// Keys and sections which were ignored by the target type so far
+    pub fn unknown(&self) -> &[Unknown] {
+        &self.unknown
+    }
+
+    // The line number of the next token
+    fn line(&self) -> usize {
+        let offset = self.source.len() - self.input.trim_start().len();
+        self.source[..offset].matches('\n').count() + 1
+    }
+
+    fn push(&mut self, segment: Segment, line: usize) {
+        self.path.push((segment, line));
+    }
+
+    fn pop(&mut self) {
+        self.path.pop();
+    }
+
+    // Locate an error at the current path
+    fn locate(&self, error: Error) -> Error {
+        let segments: Vec<Segment> = self.path.iter().map(|(s, _)| s.clone()).collect();
+        error.at(&segments, self.path.last().map(|(_, line)| *line))
+    }
+
+    // Deserialize an element of a sequence, located at its index
+    fn element<T>(&mut self, index: usize, line: usize, seed: T) -> Result<T::Value, Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        self.push(Segment::Index(index), line);
+        let result = seed.deserialize(&mut *self).map_err(|e| self.locate(e));
+        self.pop();
+        result
     }
 
     pub(crate) fn is_finished(&self) -> bool {
         self.input.is_empty()
     }
 
-    fn peek_ident(&self) -> Option<parse::de::Ident> {
+    fn peek_ident(&self) -> Option<parse::de::Ident<'de>> {
         parse::de::peek_ident(self.input)
     }
 
-    fn parse_key_like(&mut self) -> Result<parse::Key<'de>, Error> {
-        let (input, key) = parse::key_like(self.input).finish()?;
+    // A key, or a value whose grammar also allows decimals and paths
+    fn parse_scalar(&mut self) -> Result<parse::Key<'de>, Error> {
+        let (input, key) = match self.in_key {
+            true => parse::key_like(self.input).finish()?,
+            false => parse::scalar(self.input).finish()?,
+        };
         self.input = input;
         Ok(key)
     }
 
+    fn peek_empty(&self) -> bool {
+        parse::de::peek_empty(self.input)
+    }
+
+    fn check_none(&mut self) -> bool {
+        if self.peek_empty() {
+            return true;
+        }
+        match parse::scalar(self.input).finish() {
+            Ok((input, Key::Str(s))) if parse::de::peek_empty(input) && self.options.is_none(s) => {
+                self.input = input;
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn parse_ident(&mut self) -> Result<parse::de::Ident<'de>, Error> {
         let (input, ident) = parse::de::ident(self.input).finish()?;
         self.input = input;
@@ -125,12 +691,44 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    fn skip_value(&mut self) {
+        if let Ok((input, _)) = parse::de::skip_value(self.input).finish() {
+            self.input = input;
+        }
+    }
+
+    // Another [section] with the same name follows the body of the current one
+    fn peek_repeated(&self, name: &str) -> bool {
+        let mut input = self.input;
+        while !parse::de::peek_eof(input) {
+            match parse::de::peek_ident(input) {
+                Some(parse::de::Ident::Section(next)) => return next == name,
+                _ => match parse::de::skip_line(input).finish() {
+                    Ok((rest, _)) if rest.len() < input.len() => input = rest,
+                    _ => break,
+                },
+            }
+        }
+        false
+    }
+
+    fn skip_section(&mut self) {
+        while !self.check_eof() {
+            if let Some(parse::de::Ident::Section(_)) = self.peek_ident() {
+                break;
+            }
+            if let Ok((input, _)) = parse::de::skip_line(self.input).finish() {
+                self.input = input;
+            }
+        }
+    }
+
     fn done(&mut self) {
         self.input = "";
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -179,7 +777,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let value = self.parse_key_like()?;
+        let value = self.parse_scalar()?;
         match value {
             Key::Num(n) => visitor.visit_i64(n),
             Key::Str(s) => visitor.visit_i64(s.parse::<i64>()?),
@@ -190,7 +788,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let value = self.parse_key_like()?;
+        let value = self.parse_scalar()?;
         match value {
             Key::Num(n) => visitor.visit_u64(n as u64),
             Key::Str(s) => visitor.visit_u64(s.parse::<u64>()?),
@@ -211,18 +809,22 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_u64(visitor)
     }
 
-    fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::Unsupported("f32"))
+        self.deserialize_f64(visitor)
     }
 
-    fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::Unsupported("f64"))
+        let value = self.parse_scalar()?;
+        match value {
+            Key::Num(n) => visitor.visit_f64(n as f64),
+            Key::Str(s) => visitor.visit_f64(s.parse::<f64>()?),
+        }
     }
 
     fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -243,7 +845,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let value = self.parse_key_like()?;
+        if self.peek_empty() {
+            return visitor.visit_borrowed_str("");
+        }
+        let value = self.parse_scalar()?;
         match value {
             Key::Str(v) => visitor.visit_borrowed_str(v),
             Key::Num(v) => visitor.visit_str(v.to_string().as_str()),
@@ -261,7 +866,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let value = self.parse_key_like()?;
+        let value = self.parse_scalar()?;
         match value {
             Key::Str(v) => match v.chars().next() {
                 Some(c) => visitor.visit_char(c),
@@ -275,17 +880,14 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let value = self.parse_key_like()?;
-        match value {
-            Key::Str("true") => visitor.visit_bool(true),
-            Key::Str("True") => visitor.visit_bool(true),
-            Key::Str("TRUE") => visitor.visit_bool(true),
-            Key::Str("false") => visitor.visit_bool(false),
-            Key::Str("False") => visitor.visit_bool(false),
-            Key::Str("FALSE") => visitor.visit_bool(false),
-            Key::Num(0) => visitor.visit_bool(false),
-            Key::Num(1) => visitor.visit_bool(true),
-            e => Err(Error::ExpectBool(e.into())),
+        let value = self.parse_scalar()?;
+        let b = match value {
+            Key::Str(s) => self.options.to_bool(s),
+            Key::Num(n) => self.options.to_bool(&n.to_string()),
+        };
+        match b {
+            Some(b) => visitor.visit_bool(b),
+            None => Err(self.options.expect_bool(value)),
         }
     }
 
@@ -298,7 +900,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        let value = self.parse_key_like()?;
+        let value = self.parse_scalar()?;
         match value {
             Key::Str(s) => visitor.visit_enum(s.into_deserializer()),
             Key::Num(n) => visitor.visit_enum((n as u32).into_deserializer()),
@@ -328,7 +930,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_some(self)
+        match self.check_none() {
+            true => visitor.visit_none(),
+            false => visitor.visit_some(self),
+        }
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -336,6 +941,9 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: de::Visitor<'de>,
     {
         let ident = self.ident;
+        self.header = None;
+        self.element = false;
+        self.root = false;
         let value = visitor.visit_map(MapAccess { de: self, ident })?;
         Ok(value)
     }
@@ -356,10 +964,27 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(Sequence {
-            de: self,
-            first: true,
-        })
+        match self.header.take() {
+            // The body of a [section] is a sequence of repeated [section]s
+            Some(name) => {
+                let line = self
+                    .path
+                    .last()
+                    .map_or_else(|| self.line(), |(_, line)| *line);
+                visitor.visit_seq(Repeated {
+                    de: self,
+                    name,
+                    index: 0,
+                    line,
+                })
+            }
+            None => {
+                self.array = true;
+                let value = visitor.visit_seq(Sequence { de: self, index: 0 });
+                self.array = false;
+                value
+            }
+        }
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -372,6 +997,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             parse::de::Ident::Key(Key::Str(s)) => visitor.visit_borrowed_str(s),
             parse::de::Ident::Section(key) => {
                 self.ident = Some(key);
+                self.header = Some(key);
                 visitor.visit_borrowed_str(key)
             }
         }
@@ -410,21 +1036,80 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.element = false;
+        match self.header.take() {
+            Some(_) => self.skip_section(),
+            None => self.skip_value(),
+        }
+        match self.key.take() {
+            Some((parse::de::Ident::Section(name), line)) => self.unknown.push(Unknown::Section {
+                name: name.to_string(),
+                line: Some(line),
+            }),
+            Some((parse::de::Ident::Key(key), line)) => self.unknown.push(Unknown::Key {
+                section: self.ident.map(String::from),
+                key: match key {
+                    Key::Num(n) => n.to_string(),
+                    Key::Str(s) => s.to_string(),
+                },
+                line: Some(line),
+            }),
+            None => {}
+        }
+        visitor.visit_unit()
     }
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        let v = self.parse_key_like()?;
-        match v {
-            Key::Str(v) => visitor.visit_borrowed_str(v),
+        if self.root {
+            return self.deserialize_map(visitor);
+        }
+        if let Some(name) = self.header {
+            // The body of a [section] is a map, or a sequence of maps when the section repeats
+            return match !core::mem::take(&mut self.element) && self.peek_repeated(name) {
+                true => self.deserialize_seq(visitor),
+                false => self.deserialize_map(visitor),
+            };
+        }
+        if self.peek_empty() {
+            return visitor.visit_none();
+        }
+        if !self.array && parse::de::peek_array(self.input) {
+            return self.deserialize_seq(visitor);
+        }
+        match self.parse_scalar()? {
+            Key::Str(v) => visit_scalar(v, visitor),
             Key::Num(v) => visitor.visit_i64(v),
         }
     }
 }
 
+/// Visit a scalar as the most specific type it reads as: an integer, a float, `true`/`false` or a
+/// string. The bool vocabulary of [`Options`] is not used, so `y` stays a string. A map collecting
+/// keys with `#[serde(flatten)]` is buffered through here, so it needs self-describing values
+fn visit_scalar<'de, V>(s: &'de str, visitor: V) -> Result<V::Value, Error>
+where
+    V: de::Visitor<'de>,
+{
+    let numeric = |c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E');
+    if let Ok(n) = s.parse::<i64>() {
+        visitor.visit_i64(n)
+    } else if s.starts_with(|c: char| c.is_ascii_digit() || c == '-') && s.chars().all(numeric) {
+        match s.parse::<f64>() {
+            Ok(f) => visitor.visit_f64(f),
+            Err(_) => visitor.visit_borrowed_str(s),
+        }
+    } else if s.eq_ignore_ascii_case("true") {
+        visitor.visit_bool(true)
+    } else if s.eq_ignore_ascii_case("false") {
+        visitor.visit_bool(false)
+    } else {
+        visitor.visit_borrowed_str(s)
+    }
+}
+
 struct MapAccess<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
     ident: Option<&'de str>,
@@ -444,15 +1129,33 @@ impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a, 'de> {
                 match self.de.peek_ident() {
                     // A nested [section] is detected. Close this map
                     Some(parse::de::Ident::Section(_ident)) if self.ident.is_some() => Ok(None),
-                    // A root [section] is detected.
-                    Some(parse::de::Ident::Section(_ident)) => {
-                        seed.deserialize(&mut *self.de).map(Some)
+                    // A root [section] is detected. The name is the key and the body is the value
+                    Some(ident @ parse::de::Ident::Section(name)) => {
+                        let line = self.de.line();
+                        self.de.key = Some((ident, line));
+                        self.de.push(Segment::Key(name.to_string()), line);
+                        if self.de.sections.contains(&name) {
+                            return Err(self.de.locate(Error::NotAdjacent(name.to_string())));
+                        }
+                        self.de.sections.push(name);
+                        self.de.parse_ident()?;
+                        self.de.ident = Some(name);
+                        self.de.header = Some(name);
+                        seed.deserialize(BorrowedStrDeserializer::new(name))
+                            .map(Some)
+                            .map_err(|e| self.de.locate(e))
                     }
                     // A normal key, value pair is detected
-                    Some(parse::de::Ident::Key(_ident)) => {
-                        let result = seed.deserialize(&mut *self.de).map(Some)?;
-                        self.de.parse_assignment()?;
-                        Ok(result)
+                    Some(parse::de::Ident::Key(key)) => {
+                        let line = self.de.line();
+                        self.de.push(Segment::from(&key), line);
+                        self.de.key = Some((parse::de::Ident::Key(key), line));
+                        self.de.in_key = true;
+                        let key = seed.deserialize(&mut *self.de);
+                        self.de.in_key = false;
+                        key.map(Some)
+                            .and_then(|result| self.de.parse_assignment().map(|_| result))
+                            .map_err(|e| self.de.locate(e))
                     }
                     None => Err(Error::ExpectIdent(self.de.input.to_string())),
                 }
@@ -464,7 +1167,13 @@ impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a, 'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let result = seed.deserialize(&mut *self.de)?;
+        let result = seed
+            .deserialize(&mut *self.de)
+            .map_err(|e| self.de.locate(e));
+        self.de.pop();
+        let result = result?;
+        self.de.header = None;
+        self.de.key = None;
         self.de.check_eol();
         Ok(result)
     }
@@ -472,7 +1181,7 @@ impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a, 'de> {
 
 struct Sequence<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
-    first: bool,
+    index: usize,
 }
 
 impl<'de, 'a> SeqAccess<'de> for Sequence<'a, 'de> {
@@ -487,11 +1196,53 @@ impl<'de, 'a> SeqAccess<'de> for Sequence<'a, 'de> {
         } else if let Some(parse::de::Ident::Section(_)) = self.de.peek_ident() {
             Ok(None)
         } else {
-            if !self.first {
+            if self.index > 0 {
                 self.de.parse_comma()?;
             }
-            self.first = false;
-            seed.deserialize(&mut *self.de).map(Some)
+            self.index += 1;
+            let line = self.de.line();
+            self.de.element(self.index - 1, line, seed).map(Some)
+        }
+    }
+}
+
+/// Repeated [section] (or [[section]]) headers with the same name, one element per section. The
+/// sections must follow each other, see [`Error::NotAdjacent`]
+struct Repeated<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    name: &'de str,
+    index: usize,
+    // The line of the first section, whose header was already parsed
+    line: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Repeated<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.index == 0 {
+            // The header of the first section was parsed as the key of this sequence
+            self.index += 1;
+            self.de.header = Some(self.name);
+            self.de.element = true;
+            self.de.element(0, self.line, seed).map(Some)
+        } else if self.de.check_eof() {
+            Ok(None)
+        } else {
+            match self.de.peek_ident() {
+                Some(parse::de::Ident::Section(name)) if name == self.name => {
+                    let line = self.de.line();
+                    self.de.parse_ident()?;
+                    self.de.header = Some(name);
+                    self.de.element = true;
+                    self.index += 1;
+                    self.de.element(self.index - 1, line, seed).map(Some)
+                }
+                _ => Ok(None),
+            }
         }
     }
 }
@@ -1,35 +1,69 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 /// Dungeon Ini
+extern crate alloc;
+
 mod parse;
 
 #[cfg(feature = "serde")]
 pub mod de;
+pub mod overrides;
 
 use nom::Finish;
-pub use parse::{Group, Key, Sections, Value};
+pub use parse::{Document, Group, Key, Sections, Value};
 
-/// Parse the ini format file
-pub fn parse_str(input: &str) -> Result<Sections, parse::Error> {
+/// Parse the ini format file. Repeated sections are an error, see [`parse_document`] to keep them
+pub fn parse_str(input: &str) -> Result<Sections<'_>, parse::Error<'_>> {
     parse::tables(input).finish().map(|(_, sections)| sections)
 }
 
+/// Parse the ini format file, keeping the order of sections and any repeated sections
+pub fn parse_document(input: &str) -> Result<Document<'_>, parse::Error<'_>> {
+    parse::document(input)
+        .finish()
+        .map(|(_, document)| document)
+}
+
 #[cfg(feature = "serde")]
 pub fn from_str<'a, T>(input: &'a str) -> Result<T, de::Error>
 where
     T: serde::Deserialize<'a>,
 {
-    let mut deserializer = de::Deserializer::from_str(input);
-    let t = T::deserialize(&mut deserializer)?;
-    match deserializer.is_finished() {
-        true => Ok(t),
-        false => Err(de::Error::TrailingCharacters),
-    }
+    de::Options::default().from_str(input)
+}
+
+#[cfg(feature = "serde")]
+pub fn from_document<T>(document: &Document<'_>) -> Result<T, de::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    de::Options::default().from_document(document)
+}
+
+/// Deserialize a single `[section]` (and its `[section.subsection]`s) of the ini format file
+#[cfg(feature = "serde")]
+pub fn from_section<T>(input: &str, name: &str) -> Result<T, de::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    de::Options::default().from_section(input, name)
 }
 
+/// Deserialize a single `[section]` (and its `[section.subsection]`s) of parsed [`Sections`]
 #[cfg(feature = "serde")]
-pub fn from_reader<R, T>(_reader: R) -> Result<T, de::Error>
+pub fn from_sections<T>(sections: &Sections<'_>, name: &str) -> Result<T, de::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    de::Options::default().from_sections(sections, name)
+}
+
+#[cfg(all(feature = "serde", feature = "std"))]
+pub fn from_reader<R, T>(mut reader: R) -> Result<T, de::Error>
 where
     R: std::io::Read,
     T: serde::de::DeserializeOwned,
 {
-    unimplemented!()
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    from_str(&input)
 }
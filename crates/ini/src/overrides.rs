@@ -0,0 +1,212 @@
+/// overrides
+///
+/// Override values of a parsed [`Document`] without editing the ini file, IE: from the command
+/// line (`--set general.holders=Alice`) or the environment (`DUNGEON__GENERAL__HOLDERS=Alice`)
+use crate::{parse, Document, Group, Key, Value};
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use nom::Finish;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Not of the form `path=value` or `path+=value`
+    Syntax(String),
+    /// The section of the path is not a valid ini section name
+    Section(String),
+    /// The key of the path is not a valid ini key
+    Key(String),
+    /// The value is not a valid ini value
+    Value(String),
+    /// The indexed section is not in the document
+    Index(String, usize),
+}
+
+impl core::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax(s) => write!(fmt, "expected path=value, found {s:?}"),
+            Error::Section(s) => write!(fmt, "invalid section {s:?}"),
+            Error::Key(s) => write!(fmt, "invalid key {s:?}"),
+            Error::Value(s) => write!(fmt, "invalid value {s:?}"),
+            Error::Index(s, i) => write!(fmt, "no [{s}] section at index {i}"),
+        }
+    }
+}
+
+/// A `section.key=value` override of a [`Document`]
+///
+/// The key follows the last `.` of the path, which keys can not contain, so `general.fees.maker`
+/// is the `maker` key of `[general.fees]`. Keys before the first section have no section (IE:
+/// `key=value`). A repeated section is indexed from 0 (IE: `account[1].holder=Bob`), without an
+/// index every section with the name is overridden. Values are parsed with the same grammar as
+/// the ini file, so `key=a, b` is an array. Use `+=` to append to a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Override<'a> {
+    pub section: Option<&'a str>,
+    pub index: Option<usize>,
+    pub key: Key<'a>,
+    pub value: Value<'a>,
+    pub append: bool,
+}
+
+impl<'a> Override<'a> {
+    pub fn parse(input: &'a str) -> Result<Self, Error> {
+        let (path, value) = input
+            .split_once('=')
+            .ok_or_else(|| Error::Syntax(input.into()))?;
+        let (path, append) = match path.strip_suffix('+') {
+            Some(path) => (path.trim(), true),
+            None => (path.trim(), false),
+        };
+        let (section, key) = match path.rsplit_once('.') {
+            Some((section, key)) => (Some(section), key),
+            None => (None, path),
+        };
+        let (section, index) = match section.map(|s| (s, s.strip_suffix(']'))) {
+            None => (None, None),
+            Some((section, None)) => (Some(section), None),
+            Some((section, Some(indexed))) => match indexed.split_once('[') {
+                Some((name, index)) => {
+                    let index = index.parse().map_err(|_| Error::Section(section.into()))?;
+                    (Some(name), Some(index))
+                }
+                None => return Err(Error::Section(section.into())),
+            },
+        };
+        if let Some(section) = section {
+            match parse::section_name(section).finish() {
+                Ok(("", name)) if !name.is_empty() => Ok(()),
+                _ => Err(Error::Section(section.into())),
+            }?;
+        }
+        let key = match parse::key_like(key).finish() {
+            Ok(("", Key::Str(""))) | Err(_) => Err(Error::Key(key.into())),
+            Ok(("", key)) => Ok(key),
+            Ok(_) => Err(Error::Key(key.into())),
+        }?;
+        let value = match parse::value(value.trim()).finish() {
+            Ok(("", value)) => Ok(value),
+            _ => Err(Error::Value(value.into())),
+        }?;
+        Ok(Self {
+            section,
+            index,
+            key,
+            value,
+            append,
+        })
+    }
+
+    /// Apply to the indexed section, or to every section with a matching name, adding the section
+    /// when there is none
+    pub fn apply(&self, document: &mut Document<'a>) -> Result<(), Error> {
+        let Some(section) = self.section else {
+            self.apply_group(&mut document.root);
+            return Ok(());
+        };
+        let mut groups = document
+            .sections
+            .iter_mut()
+            .filter(|(n, _)| *n == section)
+            .map(|(_, group)| group);
+        match self.index {
+            Some(index) => match groups.nth(index) {
+                Some(group) => self.apply_group(group),
+                None => return Err(Error::Index(section.into(), index)),
+            },
+            None => {
+                let mut found = false;
+                for group in groups {
+                    self.apply_group(group);
+                    found = true;
+                }
+                if !found {
+                    let mut group = Group::default();
+                    self.apply_group(&mut group);
+                    document.sections.push((section, group));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_group(&self, group: &mut Group<'a>) {
+        // Keep the parsed key, which locates the line of the value in the ini file
+        let (key, previous) = match group.remove_entry(&self.key) {
+            Some((key, value)) => (key, Some(value)),
+            None => (self.key.clone(), None),
+        };
+        let value = match (self.append, previous) {
+            (true, Some(Value::Array(mut values))) => {
+                values.extend(elements(&self.value));
+                Value::Array(values)
+            }
+            (true, Some(Value::Empty)) | (true, None) | (false, _) => self.value.clone(),
+            (true, Some(value)) => {
+                let mut values = Vec::from([value]);
+                values.extend(elements(&self.value));
+                Value::Array(values)
+            }
+        };
+        group.insert(key, value);
+    }
+}
+
+fn elements<'a>(value: &Value<'a>) -> Vec<Value<'a>> {
+    match value {
+        Value::Empty => Vec::new(),
+        Value::Array(values) => values.clone(),
+        value => Vec::from([value.clone()]),
+    }
+}
+
+/// Parse and apply each `path=value` override in order
+pub fn apply<'a, I>(document: &mut Document<'a>, overrides: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    for input in overrides {
+        Override::parse(input)?.apply(document)?;
+    }
+    Ok(())
+}
+
+/// Convert `{PREFIX}__{SECTION}__{KEY}=value` variables (or `{PREFIX}__{KEY}` for keys before
+/// the first section) into lower case `section.key=value` overrides, sorted by name
+pub fn from_vars<I, K, V>(prefix: &str, vars: I) -> Vec<String>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut vars: Vec<(K, V)> = vars.into_iter().collect();
+    vars.sort_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()));
+    let mut overrides = Vec::new();
+    for (name, value) in vars.iter() {
+        let path = name
+            .as_ref()
+            .strip_prefix(prefix)
+            .and_then(|name| name.strip_prefix("__"))
+            .map(|name| name.split("__").collect::<Vec<_>>());
+        let path = match path.as_deref() {
+            Some([key]) if !key.is_empty() => key.to_lowercase(),
+            Some([section, key]) if !section.is_empty() && !key.is_empty() => {
+                alloc::format!("{}.{}", section.to_lowercase(), key.to_lowercase())
+            }
+            _ => continue,
+        };
+        overrides.push(alloc::format!("{path}={}", value.as_ref()));
+    }
+    overrides
+}
+
+/// Read overrides from the environment, see [`from_vars`]. Variables which are not unicode are
+/// skipped
+#[cfg(feature = "std")]
+pub fn from_env(prefix: &str) -> Vec<String> {
+    let vars = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    from_vars(prefix, vars)
+}
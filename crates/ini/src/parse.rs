@@ -1,32 +1,122 @@
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt, iter::FromIterator, str};
 /// parse
 use nom::{
     branch::alt,
-    bytes::complete::take_while,
+    bytes::complete::{tag, take_while},
     character::{
-        complete::{alpha1, char, digit1, line_ending, multispace0, not_line_ending, space0},
+        complete::{alpha1, char, line_ending, multispace0, not_line_ending, space0},
         is_alphanumeric, is_space,
     },
-    combinator::{map, map_res, opt, peek},
+    combinator::{eof, map, opt, peek, recognize, verify},
+    error::ErrorKind,
     multi::{many0, many1, separated_list1},
     sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
-use std::{collections::HashMap, iter::FromIterator, str};
 
-pub type Group<'a> = HashMap<Key<'a>, Value<'a>>;
+use alloc::collections::BTreeMap;
 
-pub type Sections<'a> = HashMap<&'a str, Group<'a>>;
+pub type Group<'a> = BTreeMap<Key<'a>, Value<'a>>;
+
+pub type Sections<'a> = BTreeMap<&'a str, Group<'a>>;
 
 pub type Error<'a> = nom::error::Error<&'a str>;
 
+/// An ini file which keeps its sections in order, including repeated sections
+#[derive(Debug, Clone, Default, Eq)]
+pub struct Document<'a> {
+    /// Keys before the first `[section]`
+    pub root: Group<'a>,
+    /// Every `[section]` in order of appearance
+    pub sections: Vec<(&'a str, Group<'a>)>,
+    // The text the document was parsed from, to find the line of its keys and sections
+    source: &'a str,
+}
+
+impl<'a> Document<'a> {
+    /// A document which was not parsed from ini text, so none of its keys has a line
+    pub fn new(root: Group<'a>, sections: Vec<(&'a str, Group<'a>)>) -> Self {
+        Self {
+            root,
+            sections,
+            source: "",
+        }
+    }
+
+    // A part of the document which was parsed from the same source
+    #[cfg(feature = "serde")]
+    pub(crate) fn part(&self, root: Group<'a>, sections: Vec<(&'a str, Group<'a>)>) -> Self {
+        Self {
+            root,
+            sections,
+            source: self.source,
+        }
+    }
+
+    /// Each line of the document written back as ini text, with the line of the parsed text it
+    /// came from. Keys and sections added after parsing (IE: by an override) have no line
+    pub fn lines(&self) -> Vec<(String, Option<usize>)> {
+        let mut lines = Vec::new();
+        let group = |lines: &mut Vec<_>, group: &Group<'_>| {
+            for (key, value) in group.iter() {
+                let text = match value {
+                    Value::Empty => format!("{key} ="),
+                    value => format!("{key} = {value}"),
+                };
+                let line = match key {
+                    Key::Str(s) => self.line(s),
+                    // A number has no text to locate, the value may
+                    Key::Num(_) => match value {
+                        Value::Str(s) => self.line(s),
+                        Value::Array(values) => match values.first() {
+                            Some(Value::Str(s)) => self.line(s),
+                            _ => None,
+                        },
+                        _ => None,
+                    },
+                };
+                lines.push((text, line));
+            }
+        };
+        group(&mut lines, &self.root);
+        for (i, (name, body)) in self.sections.iter().enumerate() {
+            if i > 0 || !self.root.is_empty() {
+                lines.push((String::new(), None));
+            }
+            lines.push((format!("[{name}]"), self.line(name)));
+            group(&mut lines, body);
+        }
+        lines
+    }
+
+    // The line of a slice of the parsed text
+    fn line(&self, slice: &str) -> Option<usize> {
+        let start = self.source.as_ptr() as usize;
+        let offset = (slice.as_ptr() as usize).checked_sub(start)?;
+        match offset + slice.len() <= self.source.len() {
+            true => Some(self.source[..offset].matches('\n').count() + 1),
+            false => None,
+        }
+    }
+}
+
+/// Documents are equal by their keys and sections, whatever text they were parsed from
+impl PartialEq for Document<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.sections == other.sections
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Value<'a> {
+    Empty,
     Num(i64),
     Str(&'a str),
     Array(Vec<Value<'a>>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Key<'a> {
     Num(i64),
     Str(&'a str),
@@ -44,6 +134,44 @@ impl<'a> From<&'a str> for Key<'a> {
     }
 }
 
+/// Write the document back as ini text, with its sections in order and the keys of each sorted
+impl fmt::Display for Document<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (text, _) in self.lines() {
+            writeln!(fmt, "{text}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Key<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Num(n) => write!(fmt, "{n}"),
+            Key::Str(s) => fmt.write_str(s),
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Empty => Ok(()),
+            Value::Num(n) => write!(fmt, "{n}"),
+            Value::Str(s) => fmt.write_str(s),
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        fmt.write_str(", ")?;
+                    }
+                    value.fmt(fmt)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 impl<'a> From<Key<'a>> for Value<'a> {
     fn from(value: Key<'a>) -> Self {
         match value {
@@ -67,35 +195,69 @@ pub(crate) fn space_separated(i: &str) -> IResult<&str, &str> {
     Ok((i, result.trim_end()))
 }
 
-pub(crate) fn key_like(i: &str) -> IResult<&str, Key> {
-    preceded(
-        multispace0,
-        alt((
-            map_res(digit1, |s| str::parse::<i64>(s).map(Key::Num)),
-            map(space_separated, Key::Str),
+/// A value may also hold decimals, dates and paths (IE: `0.25`, `-1`, `2024-01-01`, `in/kraken.csv`)
+pub(crate) fn value_separated(i: &str) -> IResult<&str, &str> {
+    let (i, result) = take_while(|c| {
+        is_alphanumeric(c as u8) || is_space(c as u8) || matches!(c, '_' | '.' | '-' | '/')
+    })(i)?;
+    Ok((i, result.trim_end()))
+}
+
+/// A section name, where `.` separates a subsection from its parent (IE: `general.fees`)
+pub(crate) fn section_name(i: &str) -> IResult<&str, &str> {
+    let subsection = verify(space_separated, |s: &str| !s.is_empty());
+    map(
+        recognize(pair(
+            space_separated,
+            many0(preceded(char('.'), subsection)),
         )),
+        str::trim_end,
     )(i)
 }
 
-pub(crate) fn values(i: &str) -> IResult<&str, Vec<Value>> {
-    separated_list1(char(','), map(key_like, Value::from))(i)
+fn key_or_num(s: &str) -> Key<'_> {
+    match s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse::<i64>().map_or(Key::Str(s), Key::Num),
+        false => Key::Str(s),
+    }
+}
+
+pub(crate) fn key_like(i: &str) -> IResult<&str, Key<'_>> {
+    preceded(multispace0, map(space_separated, key_or_num))(i)
+}
+
+/// A single value, IE: one element of an array
+pub(crate) fn scalar(i: &str) -> IResult<&str, Key<'_>> {
+    preceded(multispace0, map(value_separated, key_or_num))(i)
+}
+
+pub(crate) fn values(i: &str) -> IResult<&str, Vec<Value<'_>>> {
+    separated_list1(char(','), map(scalar, Value::from))(i)
+}
+
+/// Peek for the end of a value (IE: `key =` or `key = ; comment`)
+pub(crate) fn empty(i: &str) -> IResult<&str, &str> {
+    peek(preceded(space0, alt((eof, line_ending, tag(";")))))(i)
 }
 
-pub(crate) fn value(i: &str) -> IResult<&str, Value> {
-    match peek(terminated(key_like, char(',')))(i).is_ok() {
-        true => map(values, Value::Array)(i),
-        false => map(key_like, Value::from)(i),
+pub(crate) fn value(i: &str) -> IResult<&str, Value<'_>> {
+    if empty(i).is_ok() {
+        Ok((i, Value::Empty))
+    } else if peek(terminated(scalar, char(',')))(i).is_ok() {
+        map(values, Value::Array)(i)
+    } else {
+        map(scalar, Value::from)(i)
     }
 }
 
-pub(crate) fn key_value(i: &str) -> IResult<&str, (Key, Value)> {
+pub(crate) fn key_value(i: &str) -> IResult<&str, (Key<'_>, Value<'_>)> {
     pair(
         key_like,
         preceded(tuple((opt(space0), char('='), opt(space0))), value),
     )(i)
 }
 
-pub(crate) fn key_values(i: &str) -> IResult<&str, Group> {
+pub(crate) fn key_values(i: &str) -> IResult<&str, Group<'_>> {
     map(
         many0(terminated(key_value, opt(eol))),
         FromIterator::from_iter,
@@ -103,23 +265,41 @@ pub(crate) fn key_values(i: &str) -> IResult<&str, Group> {
 }
 
 pub(crate) fn section(i: &str) -> IResult<&str, (&str, Option<&str>)> {
-    delimited(
-        char('['),
-        pair(space_separated, opt(preceded(char(' '), alpha1))),
-        char(']'),
-    )(i)
+    let name = || pair(section_name, opt(preceded(char(' '), alpha1)));
+    alt((
+        delimited(tag("[["), name(), tag("]]")),
+        delimited(char('['), name(), char(']')),
+    ))(i)
 }
 
-pub(crate) fn group(i: &str) -> IResult<&str, (&str, Group)> {
+pub(crate) fn group(i: &str) -> IResult<&str, (&str, Group<'_>)> {
     pair(
         map(terminated(section, opt(eol)), |(cat, _meta)| cat),
         key_values,
     )(i)
 }
 
-pub(crate) fn tables(i: &str) -> IResult<&str, Sections> {
-    let (_i, (anon, mut named)): (_, (_, Sections)) =
-        pair(opt(key_values), map(many0(group), FromIterator::from_iter))(i)?;
+pub(crate) fn document(i: &str) -> IResult<&str, Document<'_>> {
+    map(
+        terminated(pair(key_values, many0(group)), pair(multispace0, eof)),
+        |(root, sections)| Document {
+            root,
+            sections,
+            source: i,
+        },
+    )(i)
+}
+
+/// Fails on a repeated section, whose keys would be lost. See [`document`] to keep them
+pub(crate) fn tables(i: &str) -> IResult<&str, Sections<'_>> {
+    let (mut rest, anon) = opt(key_values)(i)?;
+    let mut named = Sections::new();
+    while let Ok((next, (name, group))) = group(rest) {
+        if named.insert(name, group).is_some() {
+            return Err(nom::Err::Failure(Error::new(rest, ErrorKind::Verify)));
+        }
+        rest = next;
+    }
     if let Some(map) = anon {
         named.insert("_", map);
     }
@@ -128,15 +308,17 @@ pub(crate) fn tables(i: &str) -> IResult<&str, Sections> {
 
 #[cfg(feature = "serde")]
 pub(crate) mod de {
-    use super::{eol, key_like, section, Error, Key};
+    use super::{empty, eol, key_like, scalar, section, Error, Key};
+    use alloc::string::{String, ToString};
+    use core::{error, fmt};
     use nom::{
         branch::alt,
-        character::complete::{char, multispace0, space0},
+        bytes::complete::take_till,
+        character::complete::{char, line_ending, multispace0, not_line_ending, space0},
         combinator::{map, opt, peek},
         sequence::{preceded, terminated, tuple},
         IResult,
     };
-    use std::{error, fmt};
 
     #[derive(Debug)]
     pub struct OwnedError {
@@ -183,7 +365,7 @@ pub(crate) mod de {
         }
     }
 
-    pub(crate) fn peek_ident(i: &str) -> Option<Ident> {
+    pub(crate) fn peek_ident(i: &str) -> Option<Ident<'_>> {
         peek(opt(ident))(i).map_or(None, |(_, ident)| ident)
     }
 
@@ -195,7 +377,15 @@ pub(crate) mod de {
         peek(eol)(i).map_or_else(|_| false, |_| true)
     }
 
-    pub(crate) fn ident(i: &str) -> IResult<&str, Ident> {
+    pub(crate) fn peek_empty(i: &str) -> bool {
+        empty(i).is_ok()
+    }
+
+    pub(crate) fn peek_array(i: &str) -> bool {
+        peek(terminated(scalar, char(',')))(i).is_ok()
+    }
+
+    pub(crate) fn ident(i: &str) -> IResult<&str, Ident<'_>> {
         preceded(
             multispace0,
             alt((
@@ -209,6 +399,14 @@ pub(crate) mod de {
         multispace0(i)
     }
 
+    pub(crate) fn skip_value(i: &str) -> IResult<&str, &str> {
+        take_till(|c| matches!(c, ';' | '\r' | '\n'))(i)
+    }
+
+    pub(crate) fn skip_line(i: &str) -> IResult<&str, &str> {
+        terminated(not_line_ending, opt(line_ending))(i)
+    }
+
     pub(crate) fn assignment(i: &str) -> IResult<&str, (Option<&str>, char, Option<&str>)> {
         tuple((opt(space0), char('='), opt(space0)))(i)
     }
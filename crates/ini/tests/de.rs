@@ -128,3 +128,546 @@ fn should_deserialize() {
     assert_eq!(Enum::ThingC, test.e2);
     assert_eq!(Enum::ThingD, test.e3);
 }
+
+#[test]
+fn should_deserialize_empty_as_none() {
+    #[derive(serde::Deserialize)]
+    struct Section {
+        a: Option<u8>,
+        b: Option<String>,
+        c: Option<Vec<u8>>,
+        d: Option<u8>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Test {
+        spouse: Option<String>,
+        generator: Option<String>,
+        name: String,
+        section: Section,
+    }
+
+    let input = indoc! {r#"
+        spouse =
+        generator = ; no generator
+        name =
+
+        [section]
+        a = 
+        b = none
+        c = NULL
+        d = 4
+        "#};
+
+    let test: Test = dungeon_ini::from_str(&input.replace("NULL", "")).unwrap();
+    assert_eq!(None, test.spouse);
+    assert_eq!(None, test.generator);
+    assert_eq!("", test.name);
+    assert_eq!(None, test.section.a);
+    assert_eq!(Some("none".to_string()), test.section.b);
+    assert_eq!(None, test.section.c);
+    assert!(dungeon_ini::from_str::<Test>(input).is_err());
+
+    let options = dungeon_ini::de::Options::default().none_values(["none", "null"]);
+    let test: Test = options.from_str(input).unwrap();
+    assert_eq!(None, test.section.a);
+    assert_eq!(None, test.section.b);
+    assert_eq!(None, test.section.c);
+    assert_eq!(Some(4), test.section.d);
+}
+
+#[test]
+fn should_deserialize_bool_vocabulary() {
+    #[derive(serde::Deserialize)]
+    struct Test {
+        b0: bool,
+        b1: bool,
+        b2: bool,
+        b3: bool,
+        b4: bool,
+        b5: bool,
+    }
+
+    let input = indoc! {r#"
+        b0 = yes
+        b1 = No
+        b2 = ON
+        b3 = off
+        b4 = y
+        b5 = n
+        "#};
+    let test: Test = dungeon_ini::from_str(input).unwrap();
+    assert_eq!(
+        [true, false, true, false, true, false],
+        [test.b0, test.b1, test.b2, test.b3, test.b4, test.b5]
+    );
+
+    let options = dungeon_ini::de::Options::default().bool_values(["ja", "y", "1"], ["nein", "n"]);
+    let input = indoc! {r#"
+        b0 = ja
+        b1 = nein
+        b2 = Y
+        b3 = N
+        b4 = JA
+        b5 = yes
+        "#};
+    let err = options.from_str::<Test>(input).err().unwrap();
+    assert_eq!(
+        r#"b5 at line 6: expected bool (one of ja, y, 1, nein, n), found Str("yes")"#,
+        err.to_string()
+    );
+    let test: Test = options.from_str(&input.replace("yes", "nein")).unwrap();
+    assert_eq!(
+        [true, false, true, false, true, false],
+        [test.b0, test.b1, test.b2, test.b3, test.b4, test.b5]
+    );
+}
+
+#[test]
+fn should_deserialize_repeated_sections() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Account<'a> {
+        exchange: &'a str,
+        holder: &'a str,
+        file: Option<&'a str>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct General {
+        holders: Vec<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Test<'a> {
+        general: General,
+        #[serde(borrow)]
+        account: Vec<Account<'a>>,
+        #[serde(borrow)]
+        wallet: Vec<Account<'a>>,
+        trailing: Option<u8>,
+    }
+
+    let input = indoc! {r#"
+        [general]
+        holders = Bob, Alice
+
+        [account]
+        exchange = kraken
+        holder = Bob
+        file = kraken.csv
+
+        [account]
+        exchange = coinbase
+        holder = Alice
+
+        [[wallet]]
+        exchange = ledger
+        holder = Alice
+
+        [trailing]
+        "#};
+
+    let test: Test = dungeon_ini::from_str(input).unwrap();
+    assert_eq!(vec!["Bob", "Alice"], test.general.holders);
+    assert_eq!(
+        vec![
+            Account {
+                exchange: "kraken",
+                holder: "Bob",
+                file: Some("kraken.csv")
+            },
+            Account {
+                exchange: "coinbase",
+                holder: "Alice",
+                file: None
+            }
+        ],
+        test.account
+    );
+    assert_eq!(
+        vec![Account {
+            exchange: "ledger",
+            holder: "Alice",
+            file: None
+        }],
+        test.wallet
+    );
+    assert_eq!(None, test.trailing);
+
+    let input = indoc! {r#"
+        [account]
+        exchange = kraken
+        holder = Bob
+
+        [general]
+        holders = Bob
+
+        [account]
+        exchange = coinbase
+        holder = Alice
+        "#};
+    let err = dungeon_ini::from_str::<Test>(input).err().unwrap();
+    assert_eq!(
+        "account at line 8: [account] is repeated after other sections, repeated sections must be adjacent",
+        err.to_string()
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn should_deserialize_from_reader() {
+    #[derive(serde::Deserialize)]
+    struct General {
+        holders: Vec<String>,
+        spouse: Option<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Test {
+        general: General,
+    }
+
+    let input = indoc! {r#"
+        [general]
+        holders = Bob, Alice
+        spouse = Alice
+        "#};
+    let test: Test = dungeon_ini::from_reader(input.as_bytes()).unwrap();
+    assert_eq!(vec!["Bob", "Alice"], test.general.holders);
+    assert_eq!(Some("Alice".to_string()), test.general.spouse);
+}
+
+#[test]
+fn should_report_unknown() {
+    use dungeon_ini::de::{Error, Options, Unknown};
+
+    #[derive(serde::Deserialize)]
+    struct General {
+        exchanges: Option<Vec<String>>,
+        holders: Vec<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Test {
+        version: u8,
+        general: General,
+        trailing: Option<General>,
+    }
+
+    let input = indoc! {r#"
+        version = 1
+        debug = yes, please
+
+        [general]
+        exchange = Kraken, Coinbase ; typo
+        holders = Bob, Alice
+        generator = dungeon
+
+        [unused]
+        foo = bar
+        baz = 1, 2, 3
+
+        [trailing]
+        holders = Bob
+        "#};
+
+    let (test, unknown) = Options::default()
+        .from_str_with_unknown::<Test>(input)
+        .unwrap();
+    assert_eq!(1, test.version);
+    assert_eq!(None, test.general.exchanges);
+    assert_eq!(vec!["Bob", "Alice"], test.general.holders);
+    assert_eq!(
+        Some(vec!["Bob".to_string()]),
+        test.trailing.map(|t| t.holders)
+    );
+    assert_eq!(
+        vec![
+            Unknown::Key {
+                section: None,
+                key: "debug".into(),
+                line: Some(2)
+            },
+            Unknown::Key {
+                section: Some("general".into()),
+                key: "exchange".into(),
+                line: Some(5)
+            },
+            Unknown::Key {
+                section: Some("general".into()),
+                key: "generator".into(),
+                line: Some(7)
+            },
+            Unknown::Section {
+                name: "unused".into(),
+                line: Some(9)
+            },
+        ],
+        unknown
+    );
+
+    let test: Test = dungeon_ini::from_str(input).unwrap();
+    assert_eq!(1, test.version);
+
+    let err = Options::default()
+        .strict(true)
+        .from_str::<Test>(input)
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::Unknown(ref unknown) if unknown.len() == 4));
+    assert_eq!(
+        "unknown debug at line 2, general.exchange at line 5, general.generator at line 7, [unused] at line 9",
+        err.to_string()
+    );
+}
+
+#[test]
+fn should_deserialize_any() {
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    let input = indoc! {r#"
+        version = 2
+        debug = true
+
+        [general]
+        assets = BTC, ETH
+        fee = 0.25
+        holder = Alice
+        spouse =
+
+        [account]
+        exchange = kraken
+
+        [account]
+        exchange = coinbase
+    "#};
+
+    let value: serde_json::Value = dungeon_ini::from_str(input).unwrap();
+    assert_eq!(
+        json!({
+            "version": 2,
+            "debug": true,
+            "general": {
+                "assets": ["BTC", "ETH"],
+                "fee": 0.25,
+                "holder": "Alice",
+                "spouse": null
+            },
+            "account": [{ "exchange": "kraken" }, { "exchange": "coinbase" }]
+        }),
+        value
+    );
+    let document = dungeon_ini::parse_document(input).unwrap();
+    let from_document: serde_json::Value = dungeon_ini::from_document(&document).unwrap();
+    assert_eq!(value, from_document);
+
+    let input = indoc! {r#"
+        [general]
+        holder = Alice
+        assets = BTC
+
+        [account]
+        exchange = kraken
+    "#};
+    let sections: HashMap<String, HashMap<String, String>> = dungeon_ini::from_str(input).unwrap();
+    assert_eq!("Alice", sections["general"]["holder"]);
+    assert_eq!("BTC", sections["general"]["assets"]);
+    assert_eq!("kraken", sections["account"]["exchange"]);
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Amount {
+        Whole(u64),
+        Fraction(f64),
+        Named(String),
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Fees {
+        a: Amount,
+        b: Amount,
+        c: Amount,
+    }
+
+    let fees: Fees = dungeon_ini::from_str("a = 42\nb = 1.5\nc = free\n").unwrap();
+    assert_eq!(
+        Fees {
+            a: Amount::Whole(42),
+            b: Amount::Fraction(1.5),
+            c: Amount::Named("free".into())
+        },
+        fees
+    );
+
+    // Serde buffers the keys of a flattened map with deserialize_any, so its values are typed
+    // scalars and the map must hold a self-describing type rather than strings
+    #[derive(serde::Deserialize)]
+    struct General {
+        holders: Vec<String>,
+        #[serde(flatten)]
+        extra: HashMap<String, serde_json::Value>,
+    }
+
+    let input = "[general]\nholders = Bob\nmeta = true\nrate = 1.5\nname = x\n";
+    let test: HashMap<String, General> = dungeon_ini::from_str(input).unwrap();
+    let general = &test["general"];
+    assert_eq!(vec!["Bob"], general.holders);
+    assert_eq!(json!(true), general.extra["meta"]);
+    assert_eq!(json!(1.5), general.extra["rate"]);
+    assert_eq!(json!("x"), general.extra["name"]);
+}
+
+#[test]
+fn should_locate_errors() {
+    use dungeon_ini::de::{Error, Segment};
+    use std::collections::HashMap;
+
+    #[derive(serde::Deserialize, Debug)]
+    #[serde(rename_all = "snake_case")]
+    #[allow(dead_code)]
+    enum Method {
+        Fifo,
+        Lifo,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct General {
+        assets: Vec<u32>,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Account {
+        port: u16,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Test {
+        general: General,
+        accounting_methods: HashMap<u16, Method>,
+        #[serde(default)]
+        account: Vec<Account>,
+    }
+
+    let input = indoc! {r#"
+        [general]
+        assets = 1, 2, 3, four
+
+        [accounting_methods]
+        2022 = fifo
+    "#};
+    let err = dungeon_ini::from_str::<Test>(input).unwrap_err();
+    assert_eq!(
+        &[
+            Segment::Key("general".into()),
+            Segment::Key("assets".into()),
+            Segment::Index(3)
+        ],
+        err.path().unwrap().segments()
+    );
+    assert_eq!(Some(2), err.line());
+    assert!(matches!(err.inner(), Error::ExpectNum(_)));
+    assert!(err
+        .to_string()
+        .starts_with("general.assets[3] at line 2: expected number"));
+
+    let input = indoc! {r#"
+        [general]
+        assets = 1
+
+        [accounting_methods]
+        2021 = lifo
+        2022 = fofo
+    "#};
+    let err = dungeon_ini::from_str::<Test>(input).unwrap_err();
+    assert_eq!("accounting_methods.2022", err.path().unwrap().to_string());
+    assert_eq!(Some(6), err.line());
+    let document = dungeon_ini::parse_document(input).unwrap();
+    let err = dungeon_ini::from_document::<Test>(&document).unwrap_err();
+    assert_eq!("accounting_methods.2022", err.path().unwrap().to_string());
+    assert_eq!(Some(6), err.line());
+
+    let input = indoc! {r#"
+        [general]
+        assets = 1
+
+        [accounting_methods]
+
+        [account]
+        port = 80
+
+        [account]
+        port = http
+    "#};
+    let err = dungeon_ini::from_str::<Test>(input).unwrap_err();
+    assert!(err.to_string().starts_with("account[1].port at line 10: "));
+    let document = dungeon_ini::parse_document(input).unwrap();
+    let err = dungeon_ini::from_document::<Test>(&document).unwrap_err();
+    assert_eq!("account[1].port", err.path().unwrap().to_string());
+
+    let err = dungeon_ini::from_str::<Test>("[general]\n").unwrap_err();
+    assert_eq!("general at line 1: missing field `assets`", err.to_string());
+}
+
+#[test]
+fn should_deserialize_section() {
+    use dungeon_ini::de::Error;
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Fees {
+        maker: u32,
+        taker: u32,
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct General {
+        holders: Vec<String>,
+        fees: Option<Fees>,
+    }
+
+    let input = indoc! {r#"
+        version = 1
+
+        [general]
+        holders = Alice, Bob
+
+        [account]
+        exchange = kraken
+
+        [general.fees]
+        maker = 16
+        taker = 26
+    "#};
+    let expect = General {
+        holders: vec!["Alice".into(), "Bob".into()],
+        fees: Some(Fees {
+            maker: 16,
+            taker: 26,
+        }),
+    };
+    let general: General = dungeon_ini::from_section(input, "general").unwrap();
+    assert_eq!(expect, general);
+
+    let sections = dungeon_ini::parse_str(input).unwrap();
+    let general: General = dungeon_ini::from_sections(&sections, "general").unwrap();
+    assert_eq!(expect, general);
+
+    let fees: Fees = dungeon_ini::from_section(input, "general.fees").unwrap();
+    assert_eq!(16, fees.maker);
+
+    let err = dungeon_ini::from_section::<General>(input, "missing").unwrap_err();
+    assert!(matches!(err, Error::MissingSection(ref name) if name == "missing"));
+    assert_eq!("missing [missing] section", err.to_string());
+    let err = dungeon_ini::from_sections::<General>(&sections, "missing").unwrap_err();
+    assert!(matches!(err, Error::MissingSection(_)));
+
+    let err = dungeon_ini::from_section::<General>(
+        &input.replace("taker = 26", "taker = lots"),
+        "general",
+    )
+    .unwrap_err();
+    assert_eq!("general.fees.taker", err.path().unwrap().to_string());
+}
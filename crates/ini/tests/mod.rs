@@ -1,3 +1,4 @@
 #[cfg(feature = "serde")]
 mod de;
+mod overrides;
 mod parse;
@@ -0,0 +1,328 @@
+use dungeon_ini::{
+    overrides::{self, Error, Override},
+    parse_document, Key, Value,
+};
+use indoc::indoc;
+
+#[test]
+fn should_parse_override() {
+    assert_eq!(
+        Override {
+            section: Some("general"),
+            index: None,
+            key: Key::Str("holders"),
+            value: Value::Array(vec![Value::Str("Alice"), Value::Str("Bob")]),
+            append: false,
+        },
+        Override::parse("general.holders=Alice, Bob").unwrap()
+    );
+    assert_eq!(
+        Override {
+            section: Some("accounting_methods"),
+            index: None,
+            key: Key::Num(2024),
+            value: Value::Str("hifo"),
+            append: true,
+        },
+        Override::parse("accounting_methods.2024 += hifo").unwrap()
+    );
+    assert_eq!(
+        Override {
+            section: None,
+            index: None,
+            key: Key::Str("version"),
+            value: Value::Empty,
+            append: false,
+        },
+        Override::parse("version=").unwrap()
+    );
+    assert_eq!(
+        Override {
+            section: Some("general.fees"),
+            index: None,
+            key: Key::Str("maker"),
+            value: Value::Str("0.16"),
+            append: false,
+        },
+        Override::parse("general.fees.maker=0.16").unwrap()
+    );
+    assert_eq!(
+        Override {
+            section: Some("account"),
+            index: Some(1),
+            key: Key::Str("file"),
+            value: Value::Str("exports/coinbase.csv"),
+            append: false,
+        },
+        Override::parse("account[1].file=exports/coinbase.csv").unwrap()
+    );
+    assert_eq!(
+        Err(Error::Section("account[one]".into())),
+        Override::parse("account[one].holder=Bob")
+    );
+    assert_eq!(
+        Err(Error::Section("general..fees".into())),
+        Override::parse("general..fees.maker=1")
+    );
+    assert_eq!(
+        Err(Error::Syntax("general.holders".into())),
+        Override::parse("general.holders")
+    );
+    assert_eq!(
+        Err(Error::Key("".into())),
+        Override::parse("general.=Alice")
+    );
+    assert_eq!(
+        Err(Error::Value("Alice; Bob".into())),
+        Override::parse("general.holders=Alice; Bob")
+    );
+}
+
+#[test]
+fn should_apply_overrides() {
+    let input = indoc! {r#"
+        version = 1
+
+        [general]
+        assets = BTC
+        holders = Bob
+        exchanges = Kraken, Coinbase
+
+        [account]
+        exchange = kraken
+
+        [account]
+        exchange = coinbase
+        "#};
+    let mut document = parse_document(input).unwrap();
+    overrides::apply(
+        &mut document,
+        [
+            "version = 2",
+            "general.assets += ETH",
+            "general.holders = Alice",
+            "general.exchanges += BlockFi, Ledger",
+            "general.spouse += Alice",
+            "account.holder = Bob",
+            "account[1].exchange = ledger",
+            "accounting_methods.2024 = fifo",
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(Some(&Value::Num(2)), document.root.get(&"version".into()));
+    let (name, general) = &document.sections[0];
+    assert_eq!("general", *name);
+    assert_eq!(
+        Some(&Value::Array(vec![Value::Str("BTC"), Value::Str("ETH")])),
+        general.get(&"assets".into())
+    );
+    assert_eq!(Some(&Value::Str("Alice")), general.get(&"holders".into()));
+    assert_eq!(
+        Some(&Value::Array(vec![
+            Value::Str("Kraken"),
+            Value::Str("Coinbase"),
+            Value::Str("BlockFi"),
+            Value::Str("Ledger")
+        ])),
+        general.get(&"exchanges".into())
+    );
+    assert_eq!(Some(&Value::Str("Alice")), general.get(&"spouse".into()));
+    for (name, account) in &document.sections[1..3] {
+        assert_eq!("account", *name);
+        assert_eq!(Some(&Value::Str("Bob")), account.get(&"holder".into()));
+    }
+    let exchanges: Vec<_> = document.sections[1..3]
+        .iter()
+        .map(|(_, account)| account.get(&"exchange".into()))
+        .collect();
+    assert_eq!(
+        vec![Some(&Value::Str("kraken")), Some(&Value::Str("ledger"))],
+        exchanges
+    );
+    assert_eq!(
+        Err(Error::Index("account".into(), 2)),
+        overrides::apply(&mut document, ["account[2].holder = Alice"])
+    );
+    let (name, methods) = &document.sections[3];
+    assert_eq!("accounting_methods", *name);
+    assert_eq!(Some(&Value::Str("fifo")), methods.get(&Key::Num(2024)));
+}
+
+#[test]
+fn should_convert_env_vars() {
+    let vars = [
+        ("DUNGEON__GENERAL__HOLDERS", "Alice"),
+        ("DUNGEON__VERSION", "2"),
+        ("DUNGEON_OTHER", "skip"),
+        ("DUNGEON__A__B__C", "skip"),
+        ("HOME", "/root"),
+    ];
+    assert_eq!(
+        vec!["general.holders=Alice", "version=2"],
+        overrides::from_vars("DUNGEON", vars)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn should_deserialize_document() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Account {
+        exchange: String,
+        holder: Option<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct General {
+        assets: Vec<String>,
+        holders: Vec<String>,
+        spouse: Option<String>,
+        debug: bool,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Test {
+        version: u8,
+        general: General,
+        account: Vec<Account>,
+    }
+
+    let input = indoc! {r#"
+        version = 1
+
+        [general]
+        assets = BTC
+        holders = Bob
+        spouse =
+        debug = no
+        typo = 1
+
+        [account]
+        exchange = kraken
+
+        [account]
+        exchange = coinbase
+        "#};
+    let mut document = parse_document(input).unwrap();
+    let overrides = ["general.holders += Alice".to_string()];
+    overrides::apply(&mut document, overrides.iter().map(String::as_str)).unwrap();
+
+    let expect = indoc! {r#"
+        version = 1
+
+        [general]
+        assets = BTC
+        debug = no
+        holders = Bob, Alice
+        spouse =
+        typo = 1
+
+        [account]
+        exchange = kraken
+
+        [account]
+        exchange = coinbase
+        "#};
+    assert_eq!(expect, document.to_string());
+
+    let options = dungeon_ini::de::Options::default();
+    let (test, unknown) = options
+        .from_document_with_unknown::<Test>(&document)
+        .unwrap();
+    assert_eq!(1, test.version);
+    assert_eq!(vec!["BTC"], test.general.assets);
+    assert_eq!(vec!["Bob", "Alice"], test.general.holders);
+    assert_eq!(None, test.general.spouse);
+    assert!(!test.general.debug);
+    assert_eq!(
+        vec![
+            Account {
+                exchange: "kraken".into(),
+                holder: None
+            },
+            Account {
+                exchange: "coinbase".into(),
+                holder: None
+            }
+        ],
+        test.account
+    );
+    assert_eq!(
+        vec![dungeon_ini::de::Unknown::Key {
+            section: Some("general".into()),
+            key: "typo".into(),
+            line: Some(8)
+        }],
+        unknown
+    );
+    assert!(options
+        .strict(true)
+        .from_document::<Test>(&document)
+        .is_err());
+}
+
+#[test]
+fn should_locate_lines_of_the_ini_file() {
+    use dungeon_ini::de::Unknown;
+
+    #[derive(serde::Deserialize, Debug)]
+    struct General {
+        holders: Vec<String>,
+        debug: bool,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    struct Test {
+        general: General,
+    }
+
+    let input = indoc! {r#"
+        [general]
+        typo = 1 ; not a field
+        holders = Bob
+
+        debug = no
+        [extra]
+        "#};
+    let mut document = parse_document(input).unwrap();
+    let overrides = ["general.other = 2".to_string()];
+    overrides::apply(&mut document, overrides.iter().map(String::as_str)).unwrap();
+    let lines: Vec<Option<usize>> = document.lines().into_iter().map(|(_, l)| l).collect();
+    assert_eq!(
+        vec![Some(1), Some(5), Some(3), None, Some(2), None, Some(6)],
+        lines
+    );
+
+    let options = dungeon_ini::de::Options::default();
+    let (test, unknown) = options
+        .from_document_with_unknown::<Test>(&document)
+        .unwrap();
+    assert_eq!(vec!["Bob"], test.general.holders);
+    assert!(!test.general.debug);
+    assert_eq!(
+        vec![
+            Unknown::Key {
+                section: Some("general".into()),
+                key: "other".into(),
+                line: None
+            },
+            Unknown::Key {
+                section: Some("general".into()),
+                key: "typo".into(),
+                line: Some(2)
+            },
+            Unknown::Section {
+                name: "extra".into(),
+                line: Some(6)
+            },
+        ],
+        unknown
+    );
+
+    let overrides = ["general.debug = maybe".to_string()];
+    overrides::apply(&mut document, overrides.iter().map(String::as_str)).unwrap();
+    let err = options.from_document::<Test>(&document).unwrap_err();
+    assert_eq!("general.debug", err.path().unwrap().to_string());
+    assert_eq!(Some(5), err.line());
+}
@@ -87,3 +87,52 @@ fn should_parse() {
         );
     }
 }
+
+#[test]
+fn should_parse_empty() {
+    let input = indoc! {r#"
+        [general]
+        a =
+        b = ; comment
+        c = 42
+        d ="#};
+    let table = parse_str(input).unwrap();
+    let map = table.get("general").unwrap();
+    assert_eq!(4, map.len());
+    assert_eq!(Some(&Value::Empty), map.get(&"a".into()));
+    assert_eq!(Some(&Value::Empty), map.get(&"b".into()));
+    assert_eq!(Some(&Value::Num(42)), map.get(&"c".into()));
+    assert_eq!(Some(&Value::Empty), map.get(&"d".into()));
+}
+
+#[test]
+fn should_parse_values_and_subsections() {
+    let input = indoc! {r#"
+        [general.fees]
+        rate = 0.25
+        file = in/kraken-2024.csv
+        "#};
+    let table = parse_str(input).unwrap();
+    let map = table.get("general.fees").unwrap();
+    assert_eq!(Some(&Value::Str("0.25")), map.get(&"rate".into()));
+    assert_eq!(
+        Some(&Value::Str("in/kraken-2024.csv")),
+        map.get(&"file".into())
+    );
+
+    // Keys keep to letters, digits, spaces and underscores
+    assert!(dungeon_ini::parse_document("[general]\nfee.rate = 1\n").is_err());
+}
+
+#[test]
+fn should_not_parse_repeated_sections() {
+    let input = indoc! {r#"
+        [account]
+        holder = Bob
+        [account]
+        holder = Alice
+        "#};
+    assert!(parse_str(input).is_err());
+    let document = dungeon_ini::parse_document(input).unwrap();
+    assert_eq!(2, document.sections.len());
+}
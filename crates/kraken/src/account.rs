@@ -3,7 +3,7 @@ use std::fmt;
 /// Rest api to read account data
 /// https://docs.kraken.com/api/docs/category/rest-api/account-data
 use chrono::{DateTime, Utc};
-use dungeon_tax::Pair;
+use dungeon_tax::{Amount, Pair};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -41,21 +41,21 @@ pub struct TradesExport<'a> {
     /// Order type (market/limit)
     pub ordertype: &'a str,
     /// price
-    pub price: f32,
+    pub price: Amount,
     /// Total cost of order (quote currency)
-    pub cost: f32,
+    pub cost: Amount,
     /// Total fee (quote currency)
-    pub fee: f32,
+    pub fee: Amount,
     /// Volume (base currency)
-    pub vol: f32,
+    pub vol: Amount,
     /// Initial margin (quote currency)
-    pub margin: f32,
+    pub margin: Amount,
     /// Comma delimited list of miscellaneous info
     pub misc: &'a str,
     /// List of ledger ids for entries associated with trade
     pub ledgers: &'a str,
     /// Total cost of order (USD)
-    pub costusd: f32,
+    pub costusd: Amount,
 }
 
 /// Trade data from REST API
@@ -79,17 +79,17 @@ pub struct TradesInfo<'a> {
     /// Order type
     pub ordertype: &'a str,
     /// price
-    pub price: f32,
+    pub price: Amount,
     /// Total cost of order (quote currency)
-    pub cost: f32,
+    pub cost: Amount,
     /// Total fee (quote currency)
-    pub fee: f32,
+    pub fee: Amount,
     /// Volume (base currency)
-    pub vol: f32,
+    pub vol: Amount,
     /// Initial margin (quote currency)
-    pub margin: f32,
+    pub margin: Amount,
     /// Amount of leverage used in trade
-    pub leverage: Option<f32>,
+    pub leverage: Option<Amount>,
     /// Comma delimited list of miscellaneous info
     pub misc: &'a str,
     /// List of ledger ids for entries associated with trade
@@ -99,7 +99,7 @@ pub struct TradesInfo<'a> {
     /// True if trade was executed with user as the maker, false if take
     pub maker: Option<bool>,
     /// Total cost of order (USD)
-    pub costusd: f32,
+    pub costusd: Amount,
     /// See [`TradesInfoEx`]
     #[serde(flatten)]
     pub extra: Option<TradesInfoEx<'a>>,
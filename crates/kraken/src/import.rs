@@ -1,7 +1,11 @@
 use crate::account::{BuySell, TradesExport};
-use dungeon_tax::sheet::{self, AssetTables};
+use dungeon_tax::{
+    price::{self, PriceOracle},
+    sheet::{self, AssetTables},
+    Amount,
+};
 use std::{collections::HashMap, io};
-use tracing::trace;
+use tracing::{trace, warn};
 
 #[derive(thiserror::Error, Debug)]
 pub enum ImportError {
@@ -9,6 +13,8 @@ pub enum ImportError {
     InvalidData(#[from] csv::Error),
     #[error("Unknown Asset {0}")]
     UnknownAsset(String),
+    #[error("Trade {0} has no cost to value it in USD")]
+    NoCost(String),
 }
 
 pub fn from_reader<R, W>(
@@ -16,6 +22,36 @@ pub fn from_reader<R, W>(
     src: R,
     dst: &mut HashMap<&str, AssetTables<W>>,
 ) -> Result<(), ImportError>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    import(config, src, dst, None)
+}
+
+/// Import like [`from_reader`], pricing rows in the `quote` currency with the oracle. The price
+/// of a trade is kept when the trade is quoted in `quote`, crypto to crypto trades and trades
+/// quoted in another currency are priced by the oracle
+pub fn from_reader_with_prices<R, W>(
+    config: &sheet::Config,
+    src: R,
+    dst: &mut HashMap<&str, AssetTables<W>>,
+    prices: &dyn PriceOracle,
+    quote: &str,
+) -> Result<(), ImportError>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    import(config, src, dst, Some((prices, quote)))
+}
+
+fn import<R, W>(
+    config: &sheet::Config,
+    src: R,
+    dst: &mut HashMap<&str, AssetTables<W>>,
+    prices: Option<(&dyn PriceOracle, &str)>,
+) -> Result<(), ImportError>
 where
     R: io::Read,
     W: io::Write,
@@ -32,7 +68,7 @@ where
             date = trade.time.to_string(),
             base_currency,
             quote_currency,
-            usd = trade.costusd,
+            usd = %trade.costusd,
             type = ?trade.typ
         );
 
@@ -42,7 +78,15 @@ where
             .iter()
             .any(|s| *s == trade.pair.1.as_ref())
         {
-            let (o, i) = convert(config, &trade);
+            let (mut o, mut i) = convert(config, &trade)?;
+            if let Some((prices, quote)) = prices {
+                if !price::reprice_output(&prices, quote, &mut o) {
+                    warn!(txid = trade.txid, asset = o.asset, "missing price");
+                }
+                if !price::reprice_input(&prices, quote, &mut i) {
+                    warn!(txid = trade.txid, asset = i.asset, "missing price");
+                }
+            }
             dst.get_mut(quote_currency)
                 .ok_or_else(|| ImportError::UnknownAsset(quote_currency.to_string()))?
                 .output
@@ -52,13 +96,42 @@ where
                 .input
                 .serialize(&i)?;
         } else if trade.typ == BuySell::Buy {
-            let tx = buy(config, &trade);
+            let mut tx = buy(config, &trade)?;
+            let priced = prices.is_none_or(|(prices, quote)| match quote == quote_currency {
+                true => price::fill_input(&prices, quote, &mut tx),
+                false => {
+                    // The fee is converted from the currency of the trade, like its price
+                    let fiat_fee = std::mem::replace(&mut tx.fiat_fee, trade.fee);
+                    let priced = price::reprice_input(&prices, quote, &mut tx);
+                    if !priced {
+                        tx.fiat_fee = fiat_fee;
+                    }
+                    priced
+                }
+            });
+            if !priced {
+                warn!(txid = trade.txid, asset = tx.asset, "missing price");
+            }
             dst.get_mut(base_currency)
                 .ok_or_else(|| ImportError::UnknownAsset(base_currency.to_string()))?
                 .input
                 .serialize(&tx)?;
         } else if trade.typ == BuySell::Sell {
-            let tx = sell(config, &trade);
+            let mut tx = sell(config, &trade)?;
+            let priced = prices.is_none_or(|(prices, quote)| match quote == quote_currency {
+                true => price::fill_output(&prices, quote, &mut tx),
+                false => {
+                    let fiat_fee = tx.fiat_fee.replace(trade.fee);
+                    let priced = price::reprice_output(&prices, quote, &mut tx);
+                    if !priced {
+                        tx.fiat_fee = fiat_fee;
+                    }
+                    priced
+                }
+            });
+            if !priced {
+                warn!(txid = trade.txid, asset = tx.asset, "missing price");
+            }
             dst.get_mut(base_currency)
                 .ok_or_else(|| ImportError::UnknownAsset(base_currency.to_string()))?
                 .output
@@ -68,15 +141,25 @@ where
     Ok(())
 }
 
-fn buy<'a, 'de>(config: &'a sheet::Config, trade: &'a TradesExport<'de>) -> sheet::InputData<'a> {
-    let fiat_fee = trade.fee * trade.costusd / trade.cost;
+/// Value an amount of the quote currency in USD, at the rate implied by the cost of the trade
+fn in_fiat(trade: &TradesExport<'_>, amount: Amount) -> Result<Amount, ImportError> {
+    (amount * trade.costusd)
+        .checked_div(trade.cost)
+        .ok_or_else(|| ImportError::NoCost(trade.txid.to_string()))
+}
+
+fn buy<'a, 'de>(
+    config: &'a sheet::Config,
+    trade: &'a TradesExport<'de>,
+) -> Result<sheet::InputData<'a>, ImportError> {
+    let fiat_fee = in_fiat(trade, trade.fee)?;
     let holder = config
         .general
         .holders
-        .get(0)
+        .first()
         .map(|s| s.as_ref())
         .unwrap_or("_UNKNOWN");
-    sheet::InputData {
+    Ok(sheet::InputData {
         timestamp: trade.time,
         asset: &trade.pair.0,
         exchange: "kraken",
@@ -84,27 +167,30 @@ fn buy<'a, 'de>(config: &'a sheet::Config, trade: &'a TradesExport<'de>) -> shee
         typ: sheet::Input::Buy,
         spot_price: trade.price,
         crypto_in: trade.vol,
-        crypto_fee: Some(0.0),
+        crypto_fee: Some(Amount::ZERO),
         fiat_in_no_fee: Some(trade.costusd), // vol * price
         fiat_in_with_fee: Some(trade.costusd + fiat_fee),
         fiat_fee,
         unique_id: trade.txid,
         notes: Some(trade.ordertxid),
-    }
+    })
 }
 
-fn sell<'a, 'de>(config: &'a sheet::Config, trade: &'a TradesExport<'de>) -> sheet::OutputData<'a>
+fn sell<'a, 'de>(
+    config: &'a sheet::Config,
+    trade: &'a TradesExport<'de>,
+) -> Result<sheet::OutputData<'a>, ImportError>
 where
     'de: 'a,
 {
-    let fiat_fee = trade.fee * trade.costusd / trade.cost;
+    let fiat_fee = in_fiat(trade, trade.fee)?;
     let holder = config
         .general
         .holders
-        .get(0)
+        .first()
         .map(|s| s.as_ref())
         .unwrap_or("_UNKNOWN");
-    sheet::OutputData {
+    Ok(sheet::OutputData {
         timestamp: trade.time,
         asset: &trade.pair.0,
         exchange: "kraken",
@@ -113,36 +199,36 @@ where
         spot_price: trade.price,
         crypto_out_no_fee: trade.vol,
         crypto_out_with_fee: Some(trade.vol),
-        crypto_fee: 0.0,
+        crypto_fee: Amount::ZERO,
         fiat_out_no_fee: Some(trade.costusd),
         fiat_fee: Some(fiat_fee),
         unique_id: trade.txid,
         notes: Some(trade.ordertxid),
-    }
+    })
 }
 
 fn convert<'a, 'de>(
     config: &'a sheet::Config,
     trade: &'a TradesExport<'de>,
-) -> (sheet::OutputData<'a>, sheet::InputData<'a>) {
+) -> Result<(sheet::OutputData<'a>, sheet::InputData<'a>), ImportError> {
     let holder = config
         .general
         .holders
-        .get(0)
+        .first()
         .map(|s| s.as_str())
-        .unwrap_or(&"_UNKNOWN");
+        .unwrap_or("_UNKNOWN");
     let sell = sheet::OutputData {
         timestamp: trade.time,
         asset: &trade.pair.1,
         exchange: "kraken",
         holder,
         typ: sheet::Output::Sell,
-        spot_price: trade.costusd / trade.cost,
+        spot_price: in_fiat(trade, Amount::from(1))?,
         crypto_out_no_fee: trade.cost,
         crypto_out_with_fee: Some(trade.cost + trade.fee),
         crypto_fee: trade.fee,
         fiat_out_no_fee: Some(trade.costusd),
-        fiat_fee: Some(trade.fee * trade.costusd / trade.cost),
+        fiat_fee: Some(in_fiat(trade, trade.fee)?),
         unique_id: trade.txid,
         notes: Some(trade.ordertxid),
     };
@@ -152,14 +238,136 @@ fn convert<'a, 'de>(
         exchange: "kraken",
         holder,
         typ: sheet::Input::Buy,
-        spot_price: (trade.price * trade.costusd) / trade.cost,
+        spot_price: in_fiat(trade, trade.price)?,
         crypto_in: trade.vol,
-        crypto_fee: Some(0.0), // Captured on output side of transaction
+        crypto_fee: Some(Amount::ZERO), // Captured on output side of transaction
         fiat_in_no_fee: Some(trade.costusd),
         fiat_in_with_fee: Some(trade.costusd),
-        fiat_fee: 0.0,
+        fiat_fee: Amount::ZERO,
         unique_id: trade.txid,
         notes: Some(trade.ordertxid),
     };
-    (sell, buy)
+    Ok((sell, buy))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_reader, from_reader_with_prices, sell, ImportError};
+    use crate::account::TradesExport;
+    use chrono::{DateTime, Utc};
+    use dungeon_tax::{
+        price::PriceOracle,
+        sheet::{AssetTables, Config},
+        validate::{validate_output, Finding, Tolerance},
+        Amount,
+    };
+    use indoc::indoc;
+    use std::collections::HashMap;
+
+    const HEADER: &str =
+        "txid,ordertxid,pair,time,type,ordertype,price,cost,fee,vol,margin,misc,ledgers,costusd";
+
+    fn config() -> Config {
+        dungeon_ini::from_str(indoc! {"
+            [general]
+            assets = BTC
+            exchanges = kraken
+            holders = Bob
+
+            [in_header]
+            timestamp = 0
+            asset = 1
+            exchange = 2
+            holder = 3
+            transaction_type = 4
+            spot_price = 5
+            crypto_in = 6
+            fiat_fee = 7
+
+            [out_header]
+            timestamp = 0
+            asset = 1
+            exchange = 2
+            holder = 3
+            transaction_type = 4
+            spot_price = 5
+            crypto_out_no_fee = 6
+            crypto_fee = 7
+
+            [intra_header]
+            timestamp = 0
+            asset = 1
+            from_exchange = 2
+            from_holder = 3
+            to_exchange = 4
+            to_holder = 5
+            crypto_sent = 6
+            crypto_received = 7
+        "})
+        .unwrap()
+    }
+
+    fn tables() -> HashMap<&'static str, AssetTables<Vec<u8>>> {
+        let tables = AssetTables {
+            input: csv::Writer::from_writer(Vec::new()),
+            output: csv::Writer::from_writer(Vec::new()),
+            intra: csv::Writer::from_writer(Vec::new()),
+        };
+        HashMap::from([("BTC", tables)])
+    }
+
+    #[test]
+    fn should_reject_trade_without_cost() {
+        let trades = format!(
+            "{HEADER}\nT1,O1,BTC/USD,2022-01-01 12:00:00.0000,buy,market,40000,0,0,1,0,,,0\n"
+        );
+        match from_reader(&config(), trades.as_bytes(), &mut tables()) {
+            Err(ImportError::NoCost(txid)) => assert_eq!("T1", txid),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn should_validate_sell() {
+        // The fee of a sell is paid in the quote currency
+        let trades = format!(
+            "{HEADER}\nT1,O1,BTC/USD,2022-01-01 12:00:00.0000,sell,market,20000,10000,16,0.5,0,,,10000\n"
+        );
+        let mut reader = csv::Reader::from_reader(trades.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        let record = reader.records().next().unwrap().unwrap();
+        let trade: TradesExport = record.deserialize(Some(&headers)).unwrap();
+        let config = config();
+        let row = sell(&config, &trade).unwrap();
+        assert_eq!(Some(Amount::from(16)), row.fiat_fee);
+        let mut findings = Vec::new();
+        validate_output(0, &row, &Tolerance::default(), &mut findings);
+        assert_eq!(Vec::<Finding>::new(), findings);
+    }
+
+    /// 20000 USD for a BTC at any time
+    struct Fixed;
+
+    impl PriceOracle for Fixed {
+        fn price(&self, base: &str, quote: &str, _: DateTime<Utc>) -> Option<Amount> {
+            (base, quote)
+                .eq(&("BTC", "USD"))
+                .then(|| Amount::from(20000))
+        }
+    }
+
+    #[test]
+    fn should_keep_fee_of_repriced_sell() {
+        let trades = format!(
+            "{HEADER}\nT1,O1,BTC/EUR,2022-01-01 12:00:00.0000,sell,market,18000,9000,18,0.5,0,,,9900\n"
+        );
+        let mut tables = tables();
+        from_reader_with_prices(&config(), trades.as_bytes(), &mut tables, &Fixed, "USD").unwrap();
+        let (_, output, _) = tables.remove("BTC").unwrap().into_inner().unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let row: Vec<_> = output.lines().nth(1).unwrap().split(',').collect();
+        // spot_price, crypto_out_no_fee, crypto_fee, crypto_out_with_fee, fiat_out_no_fee and
+        // fiat_fee: the fee of 18 EUR is worth 20 USD at the new price
+        assert_eq!(["20000", "0.5", "0", "0.5", "", "20"], row[5..11]);
+    }
 }
@@ -5,10 +5,15 @@ use clap::{
     builder::{EnumValueParser, PossibleValue},
     command, value_parser, Arg, ArgAction, ValueEnum,
 };
-use dungeon_tax::sheet::{AssetTables, Config, InputData, IntraData, OutputData};
+use dungeon_ini::de::Options;
+use dungeon_tax::{
+    ods,
+    price::PriceStore,
+    sheet::{AssetTables, Config, Grid, ImportError, InputData, IntraData, OutputData},
+};
 use rust_xlsxwriter::Workbook;
 use std::{collections::HashMap, fs, iter::zip, path::PathBuf};
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::{filter::LevelFilter, fmt, layer::SubscriberExt, prelude::*};
 
 fn main() -> Result<()> {
@@ -25,12 +30,29 @@ fn main() -> Result<()> {
                 .action(ArgAction::Set)
                 .required(true),
         )
+        .arg(
+            arg!(-s --set <OVERRIDE> "Override a config value (IE: general.holders=Alice, account[1].holder=Bob)")
+                .action(ArgAction::Append)
+                .required(false),
+        )
         .arg(
             arg!(-i --input <FILE> "CSV input (stdout if empty)")
                 .value_parser(value_parser!(PathBuf))
                 .action(ArgAction::Set)
                 .required(true),
         )
+        .arg(
+            arg!(-p --prices <DIR> "OHLC candles to price rows without a reliable spot price (IE: BTC-USD.csv)")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            arg!(-q --quote <CURRENCY> "Quote currency of the candles and of the priced rows")
+                .action(ArgAction::Set)
+                .default_value("USD"),
+        )
+        .arg(arg!(--interpolate "Interpolate prices between candles").action(ArgAction::SetTrue))
         .arg(
             Arg::new("output")
                 .value_parser(value_parser!(PathBuf))
@@ -61,7 +83,27 @@ fn main() -> Result<()> {
     let path_config = matches
         .get_one::<std::path::PathBuf>("config")
         .expect("`config` missing arg");
-    let config: Config = dungeon_ini::from_str(&fs::read_to_string(path_config)?)?;
+    let config = fs::read_to_string(path_config)?;
+    let mut overrides = dungeon_ini::overrides::from_env("DUNGEON");
+    overrides.extend(
+        matches
+            .get_many::<String>("set")
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
+    let (config, unknown) = match overrides.is_empty() {
+        true => Options::default().from_str_with_unknown::<Config>(&config)?,
+        false => {
+            let mut document = dungeon_ini::parse_document(&config)
+                .map_err(|e| anyhow::anyhow!("invalid config: {e}"))?;
+            dungeon_ini::overrides::apply(&mut document, overrides.iter().map(String::as_str))?;
+            Options::default().from_document_with_unknown::<Config>(&document)?
+        }
+    };
+    for unknown in unknown {
+        warn!(%unknown, "ignored config entry");
+    }
 
     // Get a array of crypto currency ticker symbols for which we are interested in
     let assets: Vec<&'_ str> = config.general.assets.iter().map(|a| a.as_str()).collect();
@@ -85,43 +127,65 @@ fn main() -> Result<()> {
     let mut input = fs::File::open(path_input)?;
 
     // Import our csv data into the asset tables
-    let mut asset_tables: HashMap<_, _> = zip(assets, buffers).into_iter().collect();
-    dungeon_kraken::import::from_reader(&config, &mut input, &mut asset_tables)?;
-
-    // Write csv data into workbook
-    let mut workbook = Workbook::new();
-    for (asset, tables) in asset_tables.drain() {
-        // Get the buffers and create a worksheet
-        let (input, output, intra) = tables.into_inner()?;
-        let worksheet = workbook.add_worksheet().set_name(asset)?;
-
-        // Write the input table
-        worksheet.write(0, 0, "IN")?;
-        InputData::write_headers(worksheet, 1, 0, &config.in_header.0)?;
-        InputData::write_data(worksheet, input.as_slice())?;
-        // let (_, _, row, _) = worksheet.get_serialize_dimensions("InputData")?;
-        worksheet.write(2, 0, "TABLE END")?;
-
-        // Write the output table
-        // worksheet.write(row + 3, 0, "OUT")?;
-        // OutputData::write_headers(worksheet, row + 4, 0, &config.out_header.0)?;
-        // OutputData::write_data(worksheet, output.as_slice())?;
-        // let (_, _, row, _) = worksheet.get_serialize_dimensions("OutputData")?;
-        // worksheet.write(row + 1, 0, "TABLE END")?;
-
-        // // Write the intra table
-        // worksheet.write(row + 3, 0, "INTRA")?;
-        // IntraData::write_headers(worksheet, row + 4, 0, &config.intra_header.0)?;
-        // IntraData::write_data(worksheet, intra.as_slice())?;
-        // let (_, _, row, _) = worksheet.get_serialize_dimensions("IntraData")?;
-        // worksheet.write(row + 1, 0, "TABLE END")?;
+    let mut asset_tables: HashMap<_, _> = zip(assets, buffers).collect();
+    match matches.get_one::<PathBuf>("prices") {
+        Some(dir) => {
+            let prices = PriceStore::new()
+                .with_interpolation(matches.get_flag("interpolate"))
+                .open(dir)?;
+            let quote = matches
+                .get_one::<String>("quote")
+                .expect("`quote` has a default");
+            dungeon_kraken::import::from_reader_with_prices(
+                &config,
+                &mut input,
+                &mut asset_tables,
+                &prices,
+                quote,
+            )?;
+        }
+        None => dungeon_kraken::import::from_reader(&config, &mut input, &mut asset_tables)?,
     }
 
+    // Write csv data into a workbook, rp2 reads .ods files
     let output = matches
         .get_one::<PathBuf>("output")
         .expect("missing output arg");
-    workbook.save(output)?;
+    match output.extension().and_then(|ext| ext.to_str()) {
+        Some("xlsx") => {
+            let mut workbook = Workbook::new();
+            for (asset, tables) in asset_tables.drain() {
+                let worksheet = workbook.add_worksheet().set_name(asset)?;
+                write_tables(worksheet, &config, tables)?;
+            }
+            workbook.save(output)?;
+        }
+        _ => {
+            let mut workbook = ods::Workbook::new();
+            for (asset, tables) in asset_tables.drain() {
+                write_tables(workbook.add_worksheet(asset), &config, tables)?;
+            }
+            workbook.save(output)?;
+        }
+    }
+
+    Ok(())
+}
 
+/// Write the tables of an asset, separated by an empty row. The IN table is required by rp2
+fn write_tables<G>(grid: &mut G, config: &Config, tables: AssetTables<Vec<u8>>) -> Result<()>
+where
+    G: Grid,
+    ImportError: From<G::Error>,
+{
+    let (input, output, intra) = tables.into_inner()?;
+    let mut row = InputData::write_table(grid, 0, &config.in_header.0, &input)?;
+    if !output.is_empty() {
+        row = OutputData::write_table(grid, row + 2, &config.out_header.0, &output)?;
+    }
+    if !intra.is_empty() {
+        IntraData::write_table(grid, row + 2, &config.intra_header.0, &intra)?;
+    }
     Ok(())
 }
 
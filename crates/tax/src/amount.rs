@@ -0,0 +1,468 @@
+/// amount.rs
+///
+/// A decimal number for prices, volumes and fees, which is exact when parsed from text and
+/// when added, subtracted or multiplied. Division rounds to [`MAX_SCALE`] decimals.
+use serde::{
+    de::{self, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    cmp::Ordering,
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+/// The most decimals an [`Amount`] keeps, results with more decimals are rounded
+pub const MAX_SCALE: u32 = 18;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid amount {0:?}")]
+pub struct ParseAmountError(pub String);
+
+/// The value is `mantissa / 10^scale`. Trailing zeros are removed, so equal values have equal
+/// fields (IE: `1.50` and `1.5`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Amount {
+    mantissa: i128,
+    scale: u32,
+}
+
+/// The number of decimals of an asset, IE: 2 for fiat and 8 for BTC
+pub fn scale_of(asset: &str) -> u32 {
+    match asset.to_ascii_uppercase().as_str() {
+        "USD" | "EUR" | "GBP" | "CAD" | "AUD" | "CHF" => 2,
+        "JPY" => 0,
+        "ETH" => 18,
+        _ => 8,
+    }
+}
+
+fn pow10(exp: u32) -> Option<i128> {
+    10i128.checked_pow(exp)
+}
+
+// Divide rounding half away from zero
+fn div_round(n: i128, d: i128) -> i128 {
+    let (q, r) = (n / d, n % d);
+    match r.unsigned_abs() >= d.unsigned_abs() - r.unsigned_abs() {
+        true if (n < 0) != (d < 0) => q - 1,
+        true => q + 1,
+        false => q,
+    }
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    /// The amount `mantissa / 10^scale`, rounded to [`MAX_SCALE`] decimals
+    pub fn new(mantissa: i128, scale: u32) -> Self {
+        match scale > MAX_SCALE {
+            true => Self::normalize(
+                div_round(mantissa, pow10(scale - MAX_SCALE).unwrap_or(i128::MAX)),
+                MAX_SCALE,
+            ),
+            false => Self::normalize(mantissa, scale),
+        }
+    }
+
+    fn normalize(mut mantissa: i128, mut scale: u32) -> Self {
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        match mantissa {
+            0 => Self::ZERO,
+            _ => Self { mantissa, scale },
+        }
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// The number of decimals
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    pub fn abs(self) -> Self {
+        Self {
+            mantissa: self.mantissa.abs(),
+            scale: self.scale,
+        }
+    }
+
+    /// Round half away from zero to a number of decimals
+    pub fn round(self, scale: u32) -> Self {
+        match self.scale > scale {
+            true => Self::normalize(
+                div_round(
+                    self.mantissa,
+                    pow10(self.scale - scale).unwrap_or(i128::MAX),
+                ),
+                scale,
+            ),
+            false => self,
+        }
+    }
+
+    /// Round to the number of decimals of an asset, see [`scale_of`]
+    pub fn round_for(self, asset: &str) -> Self {
+        self.round(scale_of(asset))
+    }
+
+    // The mantissas of both amounts at the same scale
+    fn align(self, rhs: Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(rhs.scale);
+        let lhs = self.mantissa.checked_mul(pow10(scale - self.scale)?)?;
+        let rhs = rhs.mantissa.checked_mul(pow10(scale - rhs.scale)?)?;
+        Some((lhs, rhs, scale))
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        let (lhs, rhs, scale) = self.align(rhs)?;
+        Some(Self::normalize(lhs.checked_add(rhs)?, scale))
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        let (lhs, rhs, scale) = self.align(rhs)?;
+        Some(Self::normalize(lhs.checked_sub(rhs)?, scale))
+    }
+
+    /// Exact unless the product has more than [`MAX_SCALE`] decimals, or would overflow with
+    /// all of the decimals of both amounts
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        let (mut lhs, mut rhs) = (self, rhs);
+        loop {
+            if let Some(mantissa) = lhs.mantissa.checked_mul(rhs.mantissa) {
+                return Some(Self::new(mantissa, lhs.scale + rhs.scale));
+            }
+            // Drop the last decimal of the most precise amount until the product fits
+            match lhs.scale >= rhs.scale {
+                _ if lhs.scale == 0 && rhs.scale == 0 => return None,
+                true => lhs = lhs.round(lhs.scale - 1),
+                false => rhs = rhs.round(rhs.scale - 1),
+            }
+        }
+    }
+
+    /// Rounded to [`MAX_SCALE`] decimals, or fewer when the quotient would overflow
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+        // self.mantissa * 10^exp / rhs.mantissa has `scale` decimals
+        let mut scale = MAX_SCALE;
+        loop {
+            let exp = (scale + rhs.scale).checked_sub(self.scale);
+            let numerator = exp.and_then(|exp| self.mantissa.checked_mul(pow10(exp)?));
+            match numerator {
+                Some(numerator) => {
+                    return Some(Self::normalize(div_round(numerator, rhs.mantissa), scale))
+                }
+                None if scale == 0 || exp.is_none() => return None,
+                None => scale -= 1,
+            }
+        }
+    }
+
+    /// Lossy, for display and spreadsheets
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+
+impl From<i64> for Amount {
+    fn from(value: i64) -> Self {
+        Self::normalize(value.into(), 0)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseAmountError(s.to_string());
+        let trimmed = s.trim();
+        let (negative, digits) = match trimmed.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        // Spreadsheets write small numbers with an exponent, IE: `1E-08`
+        let (digits, exp) = match digits.split_once(['e', 'E']) {
+            Some((digits, exp)) => (digits, exp.parse::<i32>().map_err(|_| err())?),
+            None => (digits, 0),
+        };
+        let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+        let valid = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if (int.is_empty() && frac.is_empty()) || !valid(int) || !valid(frac) {
+            return Err(err());
+        }
+        let mut mantissa: i128 = 0;
+        for b in int.bytes().chain(frac.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(i128::from(b - b'0')))
+                .ok_or_else(err)?;
+        }
+        let scale = i64::try_from(frac.len()).map_err(|_| err())? - i64::from(exp);
+        let (mantissa, scale) = match u32::try_from(scale) {
+            Ok(scale) => (mantissa, scale),
+            Err(_) => {
+                let shift = u32::try_from(-scale).ok().and_then(pow10).ok_or_else(err)?;
+                (mantissa.checked_mul(shift).ok_or_else(err)?, 0)
+            }
+        };
+        Ok(Self::new(
+            if negative { -mantissa } else { mantissa },
+            scale,
+        ))
+    }
+}
+
+/// Every decimal is written unless a precision is given (IE: `{:.2}`)
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (amount, scale) = match f.precision() {
+            Some(precision) => (self.round(precision as u32), precision as u32),
+            None => (*self, self.scale),
+        };
+        let digits = amount.mantissa.unsigned_abs().to_string();
+        let padded = match digits.len() <= amount.scale as usize {
+            true => format!(
+                "{}{digits}",
+                "0".repeat(amount.scale as usize + 1 - digits.len())
+            ),
+            false => digits,
+        };
+        let (int, frac) = padded.split_at(padded.len() - amount.scale as usize);
+        if amount.mantissa < 0 {
+            f.write_str("-")?;
+        }
+        f.write_str(int)?;
+        if scale > 0 {
+            write!(f, ".{frac}{}", "0".repeat((scale - amount.scale) as usize))?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Amount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.align(*other) {
+            Some((lhs, rhs, _)) => lhs.cmp(&rhs),
+            None => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+}
+
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs).expect("amount overflow")
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.checked_sub(rhs).expect("amount overflow")
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul for Amount {
+    type Output = Amount;
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.checked_mul(rhs).expect("amount overflow")
+    }
+}
+
+impl Div for Amount {
+    type Output = Amount;
+    fn div(self, rhs: Self) -> Self::Output {
+        self.checked_div(rhs)
+            .expect("amount division by zero or overflow")
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+    fn neg(self) -> Self::Output {
+        Self {
+            mantissa: -self.mantissa,
+            scale: self.scale,
+        }
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+pub struct AmountVisitor;
+impl<'de> Visitor<'de> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a decimal number, ie: 0.12345678")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        v.parse()
+            .map_err(|_| de::Error::invalid_value(Unexpected::Str(v), &self))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Amount::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Amount::new(v.into(), 0))
+    }
+
+    /// Floats are read from their shortest decimal representation
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        v.to_string()
+            .parse()
+            .map_err(|_| de::Error::invalid_value(Unexpected::Float(v), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Read as text, so formats which infer types (IE: csv) do not round trip through f64
+        deserializer.deserialize_str(AmountVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_test::{assert_de_tokens, assert_tokens, Token};
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn should_parse() {
+        assert_eq!(Amount::new(12345678, 8), amount("0.12345678"));
+        assert_eq!(amount("1.5"), amount("1.50"));
+        assert_eq!(amount("-0.5"), amount("-.5"));
+        assert_eq!(Amount::ZERO, amount("-0.000"));
+        assert_eq!("60000", amount("60000.").to_string());
+        assert_eq!("-0.0001", amount("-0.0001").to_string());
+        assert_eq!("0.10", format!("{:.2}", amount("0.1")));
+        assert_eq!("0.13", format!("{:.2}", amount("0.125")));
+        assert_eq!("-0.13", format!("{:.2}", amount("-0.125")));
+        assert_eq!("3", format!("{:.0}", amount("2.5")));
+        assert_eq!(Amount::new(1, 8), amount("1E-08"));
+        assert_eq!(amount("-0.000000015"), amount("-1.5e-8"));
+        assert_eq!(amount("1500"), amount("1.5E3"));
+        assert_eq!(amount("200"), amount("2e+2"));
+        for invalid in ["", ".", "1.2.3", "1e", "e5", "1e5.1", "abc", "--1"] {
+            assert!(invalid.parse::<Amount>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn should_calculate_exactly() {
+        // 0.12345678 BTC at $60k, which f32 rounds
+        let cost = amount("0.12345678") * amount("60000.01");
+        assert_eq!("7407.4080345678", cost.to_string());
+        assert_eq!(amount("0.3"), amount("0.1") + amount("0.2"));
+        assert_eq!(amount("-0.1"), amount("0.1") - amount("0.2"));
+        assert_eq!(
+            "0.333333333333333333",
+            (Amount::from(1) / Amount::from(3)).to_string()
+        );
+        assert_eq!(
+            "0.666666666666666667",
+            (Amount::from(2) / Amount::from(3)).to_string()
+        );
+        assert_eq!(amount("2.5"), amount("10") / amount("4"));
+        assert_eq!(None, amount("1").checked_div(Amount::ZERO));
+        let max = Amount::new(i128::MAX, 0);
+        assert_eq!(None, max.checked_add(Amount::from(1)));
+        assert_eq!(None, max.checked_mul(Amount::from(2)));
+        assert!(amount("0.1") < amount("0.11"));
+        assert!(amount("-2") < amount("1.5"));
+        assert_eq!(
+            amount("6"),
+            [amount("1"), amount("2"), amount("3")].iter().sum()
+        );
+        assert_eq!(amount("0.12"), amount("0.123").round_for("USD"));
+        assert_eq!(amount("0.12345679"), amount("0.123456789").round_for("BTC"));
+    }
+
+    #[test]
+    fn should_serialize() {
+        assert_tokens(&amount("0.12345678"), &[Token::Str("0.12345678")]);
+        assert_de_tokens(&amount("0.1"), &[Token::F64(0.1)]);
+        assert_de_tokens(&amount("42"), &[Token::I64(42)]);
+        assert_de_tokens(&amount("42"), &[Token::U64(42)]);
+    }
+}
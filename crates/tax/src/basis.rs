@@ -0,0 +1,572 @@
+/// basis.rs
+///
+/// Match disposals against acquisition lots to compute realized gains, using the accounting
+/// method configured for the tax year of each disposal. Lots are pooled by their [`LotKey`], by
+/// default per asset across every exchange and holder, so moving coins between accounts does not
+/// change their cost basis.
+use crate::{
+    sheet::{AccountingMethod, AccountingMethods, InputData, IntraData, Output, OutputData},
+    Amount,
+};
+use chrono::{DateTime, Datelike, Utc};
+use std::{collections::HashMap, hash::Hash};
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("{unique_id} disposes {missing} {asset} more than was acquired")]
+    Insufficient {
+        asset: String,
+        unique_id: String,
+        missing: Amount,
+    },
+    #[error("{unique_id} has no spot price to value its fee of {fee} {asset}")]
+    Unpriced {
+        asset: String,
+        unique_id: String,
+        fee: Amount,
+    },
+}
+
+/// An acquisition, and how much of it was not disposed of yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot<'a> {
+    pub unique_id: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub asset: &'a str,
+    pub exchange: &'a str,
+    pub holder: &'a str,
+    pub amount: Amount,
+    pub remaining: Amount,
+    /// The fiat cost of the whole lot, including fees
+    pub cost_basis: Amount,
+    /// The part of the cost basis which was not disposed of yet
+    pub remaining_cost: Amount,
+}
+
+impl<'a> Lot<'a> {
+    pub fn unit_cost(&self) -> Amount {
+        self.cost_basis
+            .checked_div(self.amount)
+            .unwrap_or(Amount::ZERO)
+    }
+
+    // Take part of what remains of the lot, returning its cost basis. The last part takes the
+    // remaining cost, so the parts add up to the cost of the lot
+    fn take(&mut self, amount: Amount) -> Amount {
+        let cost_basis = match amount == self.remaining {
+            true => self.remaining_cost,
+            false => (self.cost_basis * amount)
+                .checked_div(self.amount)
+                .unwrap_or(Amount::ZERO)
+                .min(self.remaining_cost),
+        };
+        self.remaining -= amount;
+        self.remaining_cost -= cost_basis;
+        cost_basis
+    }
+}
+
+impl<'a> From<&InputData<'a>> for Lot<'a> {
+    fn from(input: &InputData<'a>) -> Self {
+        let cost_basis = input.fiat_in_with_fee.unwrap_or_else(|| {
+            input
+                .fiat_in_no_fee
+                .unwrap_or(input.crypto_in * input.spot_price)
+                + input.fiat_fee
+        });
+        Self {
+            unique_id: input.unique_id,
+            timestamp: input.timestamp,
+            asset: input.asset,
+            exchange: input.exchange,
+            holder: input.holder,
+            amount: input.crypto_in,
+            remaining: input.crypto_in,
+            cost_basis,
+            remaining_cost: cost_basis,
+        }
+    }
+}
+
+/// The part of a lot matched with a disposal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matched<'a> {
+    pub lot: &'a str,
+    pub acquired: DateTime<Utc>,
+    pub amount: Amount,
+    pub cost_basis: Amount,
+}
+
+/// A realized gain (or loss when negative) of a disposal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gain<'a> {
+    pub unique_id: &'a str,
+    pub timestamp: DateTime<Utc>,
+    pub asset: &'a str,
+    pub exchange: &'a str,
+    pub holder: &'a str,
+    /// The fee of an intra transfer is a [`Output::Fee`] disposal
+    pub typ: Output,
+    pub method: AccountingMethod,
+    pub amount: Amount,
+    pub proceeds: Amount,
+    pub cost_basis: Amount,
+    pub lots: Vec<Matched<'a>>,
+}
+
+impl<'a> Gain<'a> {
+    pub fn gain(&self) -> Amount {
+        self.proceeds - self.cost_basis
+    }
+}
+
+/// How lots are pooled, by the asset, exchange and holder of what acquires or disposes of them
+pub trait LotKey<'a>: Copy + Eq + Hash {
+    fn new(asset: &'a str, exchange: &'a str, holder: &'a str) -> Self;
+}
+
+/// Lots pooled per asset
+impl<'a> LotKey<'a> for &'a str {
+    fn new(asset: &'a str, _exchange: &'a str, _holder: &'a str) -> Self {
+        asset
+    }
+}
+
+/// The asset, exchange and holder of lots kept per wallet
+pub type Wallet<'a> = (&'a str, &'a str, &'a str);
+
+impl<'a> LotKey<'a> for Wallet<'a> {
+    fn new(asset: &'a str, exchange: &'a str, holder: &'a str) -> Self {
+        (asset, exchange, holder)
+    }
+}
+
+/// The lots of every asset, or of whatever else `K` pools them by
+#[derive(Debug, Default)]
+pub struct Ledger<'a, K = &'a str> {
+    methods: AccountingMethods,
+    lots: HashMap<K, Vec<Lot<'a>>>,
+}
+
+impl<'a, K: LotKey<'a>> Ledger<'a, K> {
+    pub fn new(methods: Option<&AccountingMethods>) -> Self {
+        Self {
+            methods: methods.cloned().unwrap_or_default(),
+            lots: HashMap::new(),
+        }
+    }
+
+    /// The method of a tax year, FIFO when the year is not configured
+    pub fn method(&self, year: u16) -> AccountingMethod {
+        self.methods.get(year)
+    }
+
+    /// Lots of a key which were not fully disposed of
+    pub fn lots(&self, key: K) -> &[Lot<'a>] {
+        self.lots.get(&key).map_or(&[], Vec::as_slice)
+    }
+
+    /// Keys with lots which were not fully disposed of, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.lots
+            .iter()
+            .filter(|(_, lots)| !lots.is_empty())
+            .map(|(key, _)| *key)
+    }
+
+    pub fn acquire(&mut self, input: &InputData<'a>) {
+        self.lots
+            .entry(K::new(input.asset, input.exchange, input.holder))
+            .or_default()
+            .push(Lot::from(input));
+    }
+
+    pub fn dispose(&mut self, output: &OutputData<'a>) -> Result<Gain<'a>, Error> {
+        let amount = output
+            .crypto_out_with_fee
+            .unwrap_or(output.crypto_out_no_fee + output.crypto_fee);
+        let proceeds = output
+            .fiat_out_no_fee
+            .unwrap_or(output.crypto_out_no_fee * output.spot_price);
+        self.realize(Gain {
+            unique_id: output.unique_id,
+            timestamp: output.timestamp,
+            asset: output.asset,
+            exchange: output.exchange,
+            holder: output.holder,
+            typ: output.typ,
+            method: self.method(output.timestamp.year() as u16),
+            amount,
+            proceeds,
+            cost_basis: Amount::ZERO,
+            lots: Vec::new(),
+        })
+    }
+
+    /// Transfers are not taxable, except for the fee when less is received than was sent. When
+    /// the receiving end is pooled apart from the sending one, the received amount is taken from
+    /// the sending lots by the method of the year and moved, keeping their acquisition date and
+    /// cost
+    pub fn transfer(&mut self, intra: &IntraData<'a>) -> Result<Option<Gain<'a>>, Error> {
+        let fee = intra.crypto_sent - intra.crypto_received;
+        let mut transfer = Gain {
+            unique_id: intra.unique_id,
+            timestamp: intra.timestamp,
+            asset: intra.asset,
+            exchange: intra.from_exchange,
+            holder: intra.from_holder,
+            typ: Output::Fee,
+            method: self.method(intra.timestamp.year() as u16),
+            amount: intra.crypto_received,
+            proceeds: Amount::ZERO,
+            cost_basis: Amount::ZERO,
+            lots: Vec::new(),
+        };
+        let to = K::new(intra.asset, intra.to_exchange, intra.to_holder);
+        if to != K::new(intra.asset, intra.from_exchange, intra.from_holder) {
+            let moved = self.take(&transfer)?;
+            let received = self.lots.entry(to).or_default();
+            for matched in moved {
+                received.push(Lot {
+                    unique_id: matched.lot,
+                    timestamp: matched.acquired,
+                    asset: intra.asset,
+                    exchange: intra.to_exchange,
+                    holder: intra.to_holder,
+                    amount: matched.amount,
+                    remaining: matched.amount,
+                    cost_basis: matched.cost_basis,
+                    remaining_cost: matched.cost_basis,
+                });
+            }
+        }
+        if fee <= Amount::ZERO {
+            return Ok(None);
+        }
+        let Some(spot_price) = intra.spot_price else {
+            return Err(Error::Unpriced {
+                asset: intra.asset.to_string(),
+                unique_id: intra.unique_id.to_string(),
+                fee,
+            });
+        };
+        transfer.amount = fee;
+        transfer.proceeds = fee * spot_price;
+        self.realize(transfer).map(Some)
+    }
+
+    // Match the amount of a disposal with lots acquired before it
+    fn realize(&mut self, mut gain: Gain<'a>) -> Result<Gain<'a>, Error> {
+        gain.lots = self.take(&gain)?;
+        gain.cost_basis = gain.lots.iter().map(|m| m.cost_basis).sum();
+        Ok(gain)
+    }
+
+    // Take the amount of a disposal from the lots of its key, by its method
+    fn take(&mut self, gain: &Gain<'a>) -> Result<Vec<Matched<'a>>, Error> {
+        let lots = self
+            .lots
+            .entry(K::new(gain.asset, gain.exchange, gain.holder))
+            .or_default();
+        let mut needed = gain.amount;
+        let mut matched = Vec::new();
+        while needed > Amount::ZERO {
+            let Some(lot) = select(lots, gain.method, gain.timestamp) else {
+                return Err(Error::Insufficient {
+                    asset: gain.asset.to_string(),
+                    unique_id: gain.unique_id.to_string(),
+                    missing: needed,
+                });
+            };
+            let lot = &mut lots[lot];
+            let amount = needed.min(lot.remaining);
+            let cost_basis = lot.take(amount);
+            needed -= amount;
+            matched.push(Matched {
+                lot: lot.unique_id,
+                acquired: lot.timestamp,
+                amount,
+                cost_basis,
+            });
+        }
+        lots.retain(|lot| lot.remaining > Amount::ZERO);
+        Ok(matched)
+    }
+}
+
+// The index of the next lot to dispose of with the accounting method
+fn select(lots: &[Lot], method: AccountingMethod, before: DateTime<Utc>) -> Option<usize> {
+    let lots = lots
+        .iter()
+        .enumerate()
+        .filter(|(_, lot)| lot.timestamp <= before && lot.remaining > Amount::ZERO);
+    match method {
+        AccountingMethod::Fifo => lots.min_by_key(|(_, lot)| lot.timestamp),
+        AccountingMethod::Lifo => lots.max_by_key(|(_, lot)| lot.timestamp),
+        AccountingMethod::Hifo => lots.reduce(|a, b| match b.1.unit_cost() > a.1.unit_cost() {
+            true => b,
+            false => a,
+        }),
+    }
+    .map(|(i, _)| i)
+}
+
+/// Compute the realized gains of every disposal and transfer fee, in chronological order.
+/// Acquisitions at the same time as a disposal are matched first
+pub fn compute<'a>(
+    methods: Option<&AccountingMethods>,
+    inputs: &[InputData<'a>],
+    outputs: &[OutputData<'a>],
+    intras: &[IntraData<'a>],
+) -> Result<Vec<Gain<'a>>, Error> {
+    let mut ledger: Ledger = Ledger::new(methods);
+    replay(
+        &mut ledger,
+        inputs,
+        outputs,
+        intras,
+        DateTime::<Utc>::MAX_UTC,
+    )
+}
+
+/// Apply the transactions up to and including `until` to the ledger, returning the realized
+/// gains like [`compute`]
+pub fn replay<'a, K: LotKey<'a>>(
+    ledger: &mut Ledger<'a, K>,
+    inputs: &[InputData<'a>],
+    outputs: &[OutputData<'a>],
+    intras: &[IntraData<'a>],
+    until: DateTime<Utc>,
+) -> Result<Vec<Gain<'a>>, Error> {
+    enum Event<'s, 'a> {
+        In(&'s InputData<'a>),
+        Intra(&'s IntraData<'a>),
+        Out(&'s OutputData<'a>),
+    }
+    let mut events: Vec<(DateTime<Utc>, u8, Event)> = inputs
+        .iter()
+        .map(|i| (i.timestamp, 0, Event::In(i)))
+        .chain(intras.iter().map(|i| (i.timestamp, 1, Event::Intra(i))))
+        .chain(outputs.iter().map(|o| (o.timestamp, 2, Event::Out(o))))
+        .collect();
+    events.retain(|(timestamp, _, _)| *timestamp <= until);
+    events.sort_by_key(|(timestamp, order, _)| (*timestamp, *order));
+
+    let mut gains = Vec::new();
+    for (_, _, event) in events {
+        match event {
+            Event::In(input) => ledger.acquire(input),
+            Event::Intra(intra) => gains.extend(ledger.transfer(intra)?),
+            Event::Out(output) => gains.push(ledger.dispose(output)?),
+        }
+    }
+    Ok(gains)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compute, Error};
+    use crate::{
+        sheet::{
+            AccountingMethod, AccountingMethods, Input, InputData, IntraData, Output, OutputData,
+        },
+        Amount,
+    };
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::collections::HashMap;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+    }
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn buy<'a>(
+        unique_id: &'a str,
+        timestamp: DateTime<Utc>,
+        vol: &str,
+        price: &str,
+    ) -> InputData<'a> {
+        InputData {
+            timestamp,
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: amount(price),
+            crypto_in: amount(vol),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn sell<'a>(
+        unique_id: &'a str,
+        timestamp: DateTime<Utc>,
+        vol: &str,
+        price: &str,
+    ) -> OutputData<'a> {
+        OutputData {
+            timestamp,
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: amount(price),
+            crypto_out_no_fee: amount(vol),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: None,
+            fiat_fee: None,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn methods(methods: &[(u16, AccountingMethod)]) -> AccountingMethods {
+        AccountingMethods {
+            year: methods.iter().copied().collect::<HashMap<_, _>>(),
+        }
+    }
+
+    fn matched(gain: &super::Gain) -> Vec<(String, String)> {
+        gain.lots
+            .iter()
+            .map(|m| (m.lot.to_string(), m.amount.to_string()))
+            .collect()
+    }
+
+    fn lots(lots: &[(&str, &str)]) -> Vec<(String, String)> {
+        lots.iter()
+            .map(|(lot, amount)| (lot.to_string(), amount.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn should_match_lots_by_method() {
+        let inputs = [
+            buy("b1", date(2021, 1, 1), "1", "100"),
+            buy("b2", date(2021, 2, 1), "1", "300"),
+            buy("b3", date(2021, 3, 1), "1", "200"),
+        ];
+        let outputs = [sell("s1", date(2022, 1, 1), "1.5", "400")];
+
+        let gains = compute(None, &inputs, &outputs, &[]).unwrap();
+        assert_eq!(1, gains.len());
+        assert_eq!(AccountingMethod::Fifo, gains[0].method);
+        assert_eq!(lots(&[("b1", "1"), ("b2", "0.5")]), matched(&gains[0]));
+        assert_eq!(amount("600"), gains[0].proceeds);
+        assert_eq!(amount("250"), gains[0].cost_basis);
+        assert_eq!(amount("350"), gains[0].gain());
+
+        let lifo = methods(&[(2022, AccountingMethod::Lifo)]);
+        let gains = compute(Some(&lifo), &inputs, &outputs, &[]).unwrap();
+        assert_eq!(lots(&[("b3", "1"), ("b2", "0.5")]), matched(&gains[0]));
+        assert_eq!(amount("350"), gains[0].cost_basis);
+
+        let hifo = methods(&[(2022, AccountingMethod::Hifo)]);
+        let gains = compute(Some(&hifo), &inputs, &outputs, &[]).unwrap();
+        assert_eq!(lots(&[("b2", "1"), ("b3", "0.5")]), matched(&gains[0]));
+        assert_eq!(amount("400"), gains[0].cost_basis);
+    }
+
+    #[test]
+    fn should_use_method_of_each_year() {
+        let inputs = [
+            buy("b1", date(2021, 1, 1), "1", "100"),
+            buy("b2", date(2021, 2, 1), "1", "200"),
+            buy("b3", date(2021, 3, 1), "1", "300"),
+        ];
+        let outputs = [
+            sell("s2", date(2023, 1, 1), "1", "500"),
+            sell("s1", date(2022, 1, 1), "1", "500"),
+        ];
+        let methods = methods(&[
+            (2022, AccountingMethod::Lifo),
+            (2023, AccountingMethod::Fifo),
+        ]);
+        let gains = compute(Some(&methods), &inputs, &outputs, &[]).unwrap();
+        assert_eq!("s1", gains[0].unique_id);
+        assert_eq!(lots(&[("b3", "1")]), matched(&gains[0]));
+        assert_eq!("s2", gains[1].unique_id);
+        assert_eq!(lots(&[("b1", "1")]), matched(&gains[1]));
+    }
+
+    #[test]
+    fn should_keep_exact_cost_basis() {
+        // Three equal parts of a lot add up to its cost basis, which does not divide by three
+        let inputs = [InputData {
+            fiat_in_with_fee: Some(amount("100")),
+            ..buy("b1", date(2021, 1, 1), "0.3", "300")
+        }];
+        let outputs = [
+            sell("s1", date(2022, 1, 1), "0.1", "100"),
+            sell("s2", date(2022, 1, 2), "0.1", "100"),
+            sell("s3", date(2022, 1, 3), "0.1", "100"),
+        ];
+        let gains = compute(None, &inputs, &outputs, &[]).unwrap();
+        let parts: Vec<_> = gains.iter().map(|g| g.cost_basis.to_string()).collect();
+        assert_eq!(
+            vec![
+                "33.333333333333333333",
+                "33.333333333333333333",
+                "33.333333333333333334"
+            ],
+            parts
+        );
+        let cost_basis: Amount = gains.iter().map(|g| g.cost_basis).sum();
+        assert_eq!(amount("100"), cost_basis);
+    }
+
+    #[test]
+    fn should_realize_transfer_fees() {
+        let inputs = [buy("b1", date(2021, 1, 1), "1", "100")];
+        let intra = |spot_price| IntraData {
+            timestamp: date(2021, 6, 1),
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Coinbase",
+            to_holder: "Bob",
+            spot_price,
+            crypto_sent: amount("1"),
+            crypto_received: amount("0.75"),
+            unique_id: "t1",
+            notes: None,
+        };
+        let err = compute(None, &inputs, &[], &[intra(None)]).unwrap_err();
+        assert!(matches!(err, Error::Unpriced { fee, .. } if fee == amount("0.25")));
+
+        let intras = [intra(Some(amount("300")))];
+        let outputs = [sell("s1", date(2021, 7, 1), "1", "300")];
+        let gains = compute(None, &inputs, &[], &intras).unwrap();
+        assert_eq!(1, gains.len());
+        assert_eq!(Output::Fee, gains[0].typ);
+        assert_eq!(amount("75"), gains[0].proceeds);
+        assert_eq!(amount("25"), gains[0].cost_basis);
+
+        let err = compute(None, &inputs, &outputs, &intras).unwrap_err();
+        assert_eq!(
+            Error::Insufficient {
+                asset: "BTC".into(),
+                unique_id: "s1".into(),
+                missing: amount("0.25")
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn should_not_match_later_lots() {
+        let inputs = [buy("b1", date(2022, 1, 1), "1", "100")];
+        let outputs = [sell("s1", date(2021, 1, 1), "1", "100")];
+        let err = compute(None, &inputs, &outputs, &[]).unwrap_err();
+        assert!(matches!(err, Error::Insufficient { missing, .. } if missing == amount("1")));
+    }
+}
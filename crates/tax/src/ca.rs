@@ -0,0 +1,557 @@
+/// ca.rs
+///
+/// Canadian adjusted cost base of crypto dispositions, averaged per asset across the wallets of
+/// each holder, with losses denied by the superficial loss rule
+/// https://www.canada.ca/en/revenue-agency/services/tax/individuals/topics/about-your-tax-return/tax-return/completing-a-tax-return/personal-income/line-12700-capital-gains/calculating-reporting-your-capital-gains-losses/adjusted-cost-base.html
+///
+/// The adjusted cost base is computed here rather than being an [`AccountingMethod`] of the
+/// [`Ledger`]: it averages every acquisition of the holder since the first one, and a denied loss
+/// changes the cost of later dispositions, so it cannot be chosen per tax year like the methods
+/// matching lots. Canadian filings use [`compute`] in place of [`crate::basis::compute`].
+///
+/// [`AccountingMethod`]: crate::sheet::AccountingMethod
+/// [`Ledger`]: crate::basis::Ledger
+use crate::{transaction::IntraTransaction, Amount, Transaction};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use serde::Serialize;
+use std::{collections::BTreeMap, io};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    #[error("{asset} disposition {unique_id} exceeds the holdings of {holder} by {missing}")]
+    Insufficient {
+        holder: String,
+        asset: String,
+        unique_id: String,
+        missing: Amount,
+    },
+    #[error("{asset} transfer {unique_id} has no spot price to value {amount}")]
+    Unpriced {
+        asset: String,
+        unique_id: String,
+        amount: Amount,
+    },
+}
+
+/// Days before and after a loss sale in which a purchase makes the loss superficial
+pub const SUPERFICIAL_DAYS: i64 = 30;
+
+/// A disposition, where `denied` is the superficial part of the loss, added to the adjusted cost
+/// base of the asset instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposition {
+    pub timestamp: DateTime<Utc>,
+    pub holder: String,
+    pub asset: String,
+    pub unique_id: String,
+    pub amount: Amount,
+    pub proceeds: Amount,
+    pub outlays: Amount,
+    pub acb: Amount,
+    pub denied: Amount,
+}
+
+impl Disposition {
+    /// Gain, or the loss allowed
+    pub fn gain(&self) -> Amount {
+        self.proceeds - self.acb - self.outlays + self.denied
+    }
+}
+
+/// Amount held of an asset by a holder and its adjusted cost base
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Holding {
+    pub amount: Amount,
+    pub acb: Amount,
+}
+
+impl Holding {
+    /// Adjusted cost base of part of the holding
+    fn acb_of(&self, amount: Amount) -> Amount {
+        match amount == self.amount {
+            true => self.acb,
+            false => (self.acb * amount)
+                .checked_div(self.amount)
+                .unwrap_or(Amount::ZERO),
+        }
+    }
+}
+
+/// Holder and asset of a holding
+type Key<'a> = (&'a str, &'a str);
+
+/// Change of the amount held of an asset
+struct Change {
+    timestamp: DateTime<Utc>,
+    acquired: Amount,
+    disposed: Amount,
+}
+
+/// Changes of a holding, in time order
+struct Changes(Vec<Change>);
+
+impl Changes {
+    /// Amount acquired from `start` to `end`
+    fn acquired(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Amount {
+        self.0
+            .iter()
+            .filter(|c| c.timestamp >= start && c.timestamp <= end)
+            .map(|c| c.acquired)
+            .sum()
+    }
+
+    /// Amount held at `end`
+    fn held(&self, end: DateTime<Utc>) -> Amount {
+        self.0
+            .iter()
+            .take_while(|c| c.timestamp <= end)
+            .map(|c| c.acquired - c.disposed)
+            .sum()
+    }
+}
+
+/// Value of an amount of a transfer at its spot price
+fn value(tx: &IntraTransaction, amount: Amount) -> Result<Amount, Error> {
+    match tx.spot_price {
+        Some(price) => Ok(amount * price),
+        None => Err(Error::Unpriced {
+            asset: tx.asset.clone(),
+            unique_id: tx.unique_id.clone(),
+            amount,
+        }),
+    }
+}
+
+/// What is acquired by a transaction: holding, amount and cost
+fn acquired(tx: &Transaction) -> Result<Option<(Key<'_>, Amount, Amount)>, Error> {
+    match tx {
+        Transaction::In(tx) => {
+            let cost = match tx.fiat_in_with_fee {
+                Some(cost) => cost,
+                None => tx.crypto_in * tx.spot_price + tx.fiat_fee,
+            };
+            Ok(Some(((&tx.holder, &tx.asset), tx.crypto_in, cost)))
+        }
+        Transaction::Out(_) => Ok(None),
+        Transaction::Intra(tx) if tx.from_holder == tx.to_holder => Ok(None),
+        Transaction::Intra(tx) => {
+            let amount = tx.crypto_received;
+            Ok(Some((
+                (&tx.to_holder, &tx.asset),
+                amount,
+                value(tx, amount)?,
+            )))
+        }
+    }
+}
+
+/// What is disposed of by a transaction: holding, amount, proceeds and outlays
+fn disposed(tx: &Transaction) -> Result<Option<(Key<'_>, Amount, Amount, Amount)>, Error> {
+    match tx {
+        Transaction::In(_) => Ok(None),
+        Transaction::Out(tx) => {
+            let fee = tx.fiat_fee.unwrap_or(tx.crypto_fee * tx.spot_price);
+            let proceeds = tx
+                .fiat_out_no_fee
+                .unwrap_or(tx.crypto_out_no_fee * tx.spot_price);
+            let amount = tx.crypto_out_no_fee + tx.crypto_fee;
+            Ok(Some(((&tx.holder, &tx.asset), amount, proceeds + fee, fee)))
+        }
+        Transaction::Intra(tx) => {
+            let amount = match tx.from_holder == tx.to_holder {
+                true => tx.crypto_sent - tx.crypto_received,
+                false => tx.crypto_sent,
+            };
+            if amount <= Amount::ZERO {
+                return Ok(None);
+            }
+            let key = (tx.from_holder.as_str(), tx.asset.as_str());
+            Ok(Some((key, amount, value(tx, amount)?, Amount::ZERO)))
+        }
+    }
+}
+
+/// Dispositions in time order, the cost of an acquisition including its fiat fee.
+///
+/// The fee of a disposition is disposed of at its value and reported as an outlay. The fee of a
+/// transfer between wallets is disposed of at its value without outlays, since nothing is sold.
+/// A transfer to another holder is a gift, disposed of by the sender and acquired by the
+/// receiver at its value. A transfer which is valued needs a spot price. A loss is superficial in proportion to the least of the amount sold,
+/// the amount acquired within 30 days before or after the sale, and the amount held 30 days after
+/// the sale. The denied loss is added to the adjusted cost base of the asset at the sale.
+pub fn compute(transactions: &[Transaction]) -> Result<Vec<Disposition>, Error> {
+    let mut sorted: Vec<&Transaction> = transactions.iter().collect();
+    sorted.sort();
+
+    let mut changes: BTreeMap<Key, Changes> = BTreeMap::new();
+    for tx in sorted.iter() {
+        let timestamp = tx.timestamp();
+        let mut push = |key, acquired, disposed| {
+            changes
+                .entry(key)
+                .or_insert_with(|| Changes(Vec::new()))
+                .0
+                .push(Change {
+                    timestamp,
+                    acquired,
+                    disposed,
+                })
+        };
+        if let Some((key, amount, ..)) = disposed(tx)? {
+            push(key, Amount::ZERO, amount);
+        }
+        if let Some((key, amount, _)) = acquired(tx)? {
+            push(key, amount, Amount::ZERO);
+        }
+    }
+
+    let window = Duration::days(SUPERFICIAL_DAYS);
+    let mut holdings: BTreeMap<Key, Holding> = BTreeMap::new();
+    let mut dispositions = Vec::new();
+    for tx in sorted.iter() {
+        if let Some((key, amount, proceeds, outlays)) = disposed(tx)? {
+            let holding = holdings.entry(key).or_default();
+            if amount > holding.amount {
+                return Err(Error::Insufficient {
+                    holder: key.0.to_string(),
+                    asset: key.1.to_string(),
+                    unique_id: tx.unique_id().to_string(),
+                    missing: amount - holding.amount,
+                });
+            }
+            let acb = holding.acb_of(amount);
+            holding.amount -= amount;
+            holding.acb -= acb;
+
+            let loss = acb + outlays - proceeds;
+            let mut denied = Amount::ZERO;
+            if loss > Amount::ZERO {
+                let timestamp = tx.timestamp();
+                let changes = &changes[&key];
+                let substituted = amount
+                    .min(changes.acquired(timestamp - window, timestamp + window))
+                    .min(changes.held(timestamp + window));
+                if substituted > Amount::ZERO {
+                    denied = (loss * substituted)
+                        .checked_div(amount)
+                        .unwrap_or(Amount::ZERO);
+                    holding.acb += denied;
+                }
+            }
+            dispositions.push(Disposition {
+                timestamp: tx.timestamp(),
+                holder: key.0.to_string(),
+                asset: key.1.to_string(),
+                unique_id: tx.unique_id().to_string(),
+                amount,
+                proceeds,
+                outlays,
+                acb,
+                denied,
+            });
+        }
+        if let Some((key, amount, cost)) = acquired(tx)? {
+            let holding = holdings.entry(key).or_default();
+            holding.amount += amount;
+            holding.acb += cost;
+        }
+    }
+    Ok(dispositions)
+}
+
+/// A line of the capital gains schedule (T1 Schedule 3), amounts in dollars and cents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Line {
+    pub description: String,
+    pub date: String,
+    pub proceeds: Amount,
+    pub acb: Amount,
+    pub outlays: Amount,
+    pub superficial_loss: Amount,
+    pub gain: Amount,
+}
+
+impl Line {
+    pub const HEADERS: [&'static str; 7] = [
+        "description",
+        "date",
+        "proceeds",
+        "acb",
+        "outlays",
+        "superficial_loss",
+        "gain",
+    ];
+}
+
+/// Dispositions of a holder in a tax year, in time order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub year: i32,
+    pub lines: Vec<Line>,
+}
+
+impl Schedule {
+    pub fn new(dispositions: &[Disposition], holder: &str, year: i32) -> Self {
+        let lines = dispositions
+            .iter()
+            .filter(|d| d.holder == holder && d.timestamp.year() == year)
+            .map(|d| {
+                let proceeds = d.proceeds.round(2);
+                let acb = d.acb.round(2);
+                let outlays = d.outlays.round(2);
+                let superficial_loss = d.denied.round(2);
+                Line {
+                    description: format!("{} {}", d.amount, d.asset),
+                    date: d.timestamp.format("%Y-%m-%d").to_string(),
+                    proceeds,
+                    acb,
+                    outlays,
+                    superficial_loss,
+                    gain: proceeds - acb - outlays + superficial_loss,
+                }
+            })
+            .collect();
+        Self { year, lines }
+    }
+
+    /// Net gain, or loss, of the year
+    pub fn total(&self) -> Amount {
+        self.lines.iter().map(|line| line.gain).sum()
+    }
+
+    pub fn write_csv<W>(&self, writer: W) -> Result<(), csv::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for line in self.lines.iter() {
+            writer.serialize(line)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compute, Error, Schedule};
+    use crate::{
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        Amount, Transaction,
+    };
+    use chrono::{TimeZone, Utc};
+    use indoc::indoc;
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn buy(unique_id: &str, month: u32, day: u32, vol: &str, cost: &str) -> Transaction {
+        Transaction::from(InputData {
+            timestamp: Utc.with_ymd_and_hms(2022, month, day, 0, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: Amount::ZERO,
+            crypto_in: amount(vol),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: Some(amount(cost)),
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: None,
+        })
+    }
+
+    fn sell(unique_id: &str, month: u32, day: u32, vol: &str, proceeds: &str) -> Transaction {
+        Transaction::from(OutputData {
+            timestamp: Utc.with_ymd_and_hms(2022, month, day, 12, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: Amount::ZERO,
+            crypto_out_no_fee: amount(vol),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: Some(amount(proceeds)),
+            fiat_fee: Some(Amount::ZERO),
+            unique_id,
+            notes: None,
+        })
+    }
+
+    #[test]
+    fn should_average_cost() {
+        let dispositions = compute(&[
+            buy("b1", 1, 1, "1", "100"),
+            buy("b2", 2, 1, "3", "500"),
+            sell("s1", 3, 1, "2", "400"),
+            sell("s2", 4, 1, "2", "250"),
+        ])
+        .unwrap();
+        let found: Vec<_> = dispositions.iter().map(|d| (d.acb, d.gain())).collect();
+        assert_eq!(
+            vec![
+                (amount("300"), amount("100")),
+                (amount("300"), amount("-50"))
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn should_deny_superficial_loss() {
+        // Half of the units sold at a loss are bought back within 30 days and still held
+        let transactions = [
+            buy("b1", 1, 1, "2", "400"),
+            sell("s1", 3, 1, "2", "200"),
+            buy("b2", 3, 20, "1", "90"),
+            sell("s2", 6, 1, "1", "150"),
+        ];
+        let dispositions = compute(&transactions).unwrap();
+        assert_eq!(amount("100"), dispositions[0].denied);
+        assert_eq!(amount("-100"), dispositions[0].gain());
+        // Denied loss added to the cost of the replacement
+        assert_eq!(amount("190"), dispositions[1].acb);
+        assert_eq!(amount("-40"), dispositions[1].gain());
+
+        let schedule = Schedule::new(&dispositions, "Bob", 2022);
+        assert_eq!(amount("-140"), schedule.total());
+        let mut csv = Vec::new();
+        schedule.write_csv(&mut csv).unwrap();
+        let expect = indoc! {"
+            description,date,proceeds,acb,outlays,superficial_loss,gain
+            2 BTC,2022-03-01,200,400,0,100,-100
+            1 BTC,2022-06-01,150,190,0,0,-40
+        "};
+        assert_eq!(expect, String::from_utf8(csv).unwrap());
+    }
+
+    #[test]
+    fn should_allow_loss_when_sold_again() {
+        // Bought back within 30 days but nothing held 30 days after the sale
+        let dispositions = compute(&[
+            buy("b1", 1, 1, "1", "200"),
+            sell("s1", 3, 1, "1", "100"),
+            buy("b2", 3, 10, "1", "90"),
+            sell("s2", 3, 20, "1", "95"),
+        ])
+        .unwrap();
+        assert_eq!(Amount::ZERO, dispositions[0].denied);
+        assert_eq!(amount("-100"), dispositions[0].gain());
+    }
+
+    #[test]
+    fn should_not_dispose_more_than_held() {
+        let transactions = [buy("b1", 1, 1, "1", "100"), sell("s1", 2, 1, "2", "500")];
+        assert_eq!(
+            Err(Error::Insufficient {
+                holder: "Bob".into(),
+                asset: "BTC".into(),
+                unique_id: "s1".into(),
+                missing: amount("1")
+            }),
+            compute(&transactions)
+        );
+    }
+
+    fn send(
+        unique_id: &str,
+        month: u32,
+        to_holder: &str,
+        spot_price: Option<&str>,
+        sent: &str,
+    ) -> Transaction {
+        Transaction::from(IntraData {
+            timestamp: Utc.with_ymd_and_hms(2022, month, 1, 6, 0, 0).unwrap(),
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Ledger",
+            to_holder,
+            spot_price: spot_price.map(amount),
+            crypto_sent: amount(sent),
+            crypto_received: amount("1"),
+            unique_id,
+            notes: None,
+        })
+    }
+
+    #[test]
+    fn should_dispose_transfer_fee_and_gift() {
+        // Alice sells what Bob gave her
+        let gifted = Transaction::from(OutputData {
+            timestamp: Utc.with_ymd_and_hms(2022, 4, 1, 12, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Ledger",
+            holder: "Alice",
+            typ: Output::Sell,
+            spot_price: Amount::ZERO,
+            crypto_out_no_fee: amount("1"),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: Some(amount("300")),
+            fiat_fee: Some(Amount::ZERO),
+            unique_id: "s1",
+            notes: None,
+        });
+        let dispositions = compute(&[
+            buy("b1", 1, 1, "4", "400"),
+            send("t1", 2, "Bob", Some("200"), "1.5"),
+            send("t2", 3, "Alice", Some("200"), "1.5"),
+            gifted,
+        ])
+        .unwrap();
+        let found: Vec<_> = dispositions
+            .iter()
+            .map(|d| (d.holder.as_str(), d.amount, d.proceeds, d.outlays, d.acb))
+            .collect();
+        // The fee of a transfer between Bob's wallets is sold at its value, without outlays, and
+        // the gift to Alice at the value of what was sent, Alice's cost being what she received
+        assert_eq!(
+            vec![
+                (
+                    "Bob",
+                    amount("0.5"),
+                    amount("100"),
+                    amount("0"),
+                    amount("50")
+                ),
+                (
+                    "Bob",
+                    amount("1.5"),
+                    amount("300"),
+                    amount("0"),
+                    amount("150")
+                ),
+                (
+                    "Alice",
+                    amount("1"),
+                    amount("300"),
+                    amount("0"),
+                    amount("200")
+                ),
+            ],
+            found
+        );
+        assert_eq!(amount("50"), dispositions[0].gain());
+    }
+
+    #[test]
+    fn should_not_value_gift_without_price() {
+        let transactions = [
+            buy("b1", 1, 1, "2", "200"),
+            send("t1", 2, "Alice", None, "1"),
+        ];
+        assert_eq!(
+            Err(Error::Unpriced {
+                asset: "BTC".into(),
+                unique_id: "t1".into(),
+                amount: amount("1")
+            }),
+            compute(&transactions)
+        );
+    }
+}
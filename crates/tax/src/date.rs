@@ -4,11 +4,16 @@ use serde::{Deserialize, Deserializer, Serializer};
 const FORMAT: &str = "%Y-%m-%d %H:%M:%S.%f";
 const FORMAT_TZ: &str = "%Y-%m-%d %H:%M:%S.%f%:z";
 
+/// Format of a date in a table cell
+pub fn format(date: &DateTime<Utc>) -> impl std::fmt::Display + '_ {
+    date.format(FORMAT_TZ)
+}
+
 pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    serializer.serialize_str(&format!("{}", date.format(FORMAT_TZ)))
+    serializer.collect_str(&format(date))
 }
 
 pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
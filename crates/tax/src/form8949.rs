@@ -0,0 +1,475 @@
+/// form8949.rs
+///
+/// IRS Form 8949 line items of realized gains, and the totals carried to Schedule D
+/// https://www.irs.gov/forms-pubs/about-form-8949
+use crate::{basis::Gain, report::Term, sheet::Output, Amount};
+use chrono::{Datelike, NaiveDate};
+use serde::{Serialize, Serializer};
+use std::{collections::BTreeMap, fmt, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum Part {
+    /// Short term
+    I,
+    /// Long term
+    II,
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::I => f.write_str("I"),
+            Self::II => f.write_str("II"),
+        }
+    }
+}
+
+/// How a disposal was reported to the IRS by the exchange
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Reporting {
+    /// On a 1099-B showing this cost basis, which is corrected with code B when it is not the
+    /// cost basis of the lots
+    BasisReported(Amount),
+    /// On a 1099-B without the cost basis
+    BasisNotReported,
+    /// Not on a 1099-B
+    #[default]
+    NotReported,
+}
+
+/// The box checked on the form, A to C for short and D to F for long term
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum Category {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl Category {
+    pub fn new(term: Term, reporting: Reporting) -> Self {
+        match (term, reporting) {
+            (Term::Short, Reporting::BasisReported(_)) => Self::A,
+            (Term::Short, Reporting::BasisNotReported) => Self::B,
+            (Term::Short, Reporting::NotReported) => Self::C,
+            (Term::Long, Reporting::BasisReported(_)) => Self::D,
+            (Term::Long, Reporting::BasisNotReported) => Self::E,
+            (Term::Long, Reporting::NotReported) => Self::F,
+        }
+    }
+
+    pub fn part(&self) -> Part {
+        match self {
+            Self::A | Self::B | Self::C => Part::I,
+            Self::D | Self::E | Self::F => Part::II,
+        }
+    }
+
+    /// Line of Schedule D the totals of the box are carried to
+    pub fn line(&self) -> &'static str {
+        match self {
+            Self::A => "1b",
+            Self::B => "2",
+            Self::C => "3",
+            Self::D => "8b",
+            Self::E => "9",
+            Self::F => "10",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+fn us_date<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(&date.format("%m/%d/%Y"))
+}
+
+/// A line of the form, amounts in dollars and cents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Line {
+    pub part: Part,
+    #[serde(rename = "box")]
+    pub category: Category,
+    pub description: String,
+    #[serde(serialize_with = "us_date")]
+    pub date_acquired: NaiveDate,
+    #[serde(serialize_with = "us_date")]
+    pub date_sold: NaiveDate,
+    pub proceeds: Amount,
+    pub cost_basis: Amount,
+    pub code: String,
+    pub adjustment: Amount,
+    pub gain: Amount,
+}
+
+impl Line {
+    pub const HEADERS: [&'static str; 10] = [
+        "part",
+        "box",
+        "description",
+        "date_acquired",
+        "date_sold",
+        "proceeds",
+        "cost_basis",
+        "code",
+        "adjustment",
+        "gain",
+    ];
+}
+
+/// Totals of a line of Schedule D
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Total {
+    pub line: &'static str,
+    pub proceeds: Amount,
+    pub cost_basis: Amount,
+    pub adjustment: Amount,
+    pub gain: Amount,
+}
+
+impl Total {
+    pub const HEADERS: [&'static str; 5] = ["line", "proceeds", "cost_basis", "adjustment", "gain"];
+
+    fn new(line: &'static str) -> Self {
+        Self {
+            line,
+            proceeds: Amount::ZERO,
+            cost_basis: Amount::ZERO,
+            adjustment: Amount::ZERO,
+            gain: Amount::ZERO,
+        }
+    }
+
+    fn add(&mut self, line: &Line) {
+        self.proceeds += line.proceeds;
+        self.cost_basis += line.cost_basis;
+        self.adjustment += line.adjustment;
+        self.gain += line.gain;
+    }
+}
+
+/// Lines of a tax year, sorted by part, box and date sold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Form8949 {
+    pub year: i32,
+    pub lines: Vec<Line>,
+}
+
+impl Form8949 {
+    /// A line for each lot matched by a sale of the tax year, `reporting` gives how the exchange
+    /// reported each sale. Gifts, donations, losses and fees are not sales, so they have no line.
+    /// A reported cost basis is shared between the lines of a sale by their amount
+    pub fn new<F>(gains: &[Gain], year: i32, reporting: F) -> Self
+    where
+        F: Fn(&Gain) -> Reporting,
+    {
+        let mut lines = Vec::new();
+        let sales = gains
+            .iter()
+            .filter(|gain| gain.typ == Output::Sell && gain.timestamp.year() == year);
+        for gain in sales {
+            let reporting = reporting(gain);
+            let mut reported = match reporting {
+                Reporting::BasisReported(basis) if basis.round(2) != gain.cost_basis.round(2) => {
+                    Some(basis)
+                }
+                _ => None,
+            };
+            let last = gain.lots.len().saturating_sub(1);
+            for (n, matched) in gain.lots.iter().enumerate() {
+                let category = Category::new(matched.term(gain.timestamp), reporting);
+                let proceeds = gain.proceeds_of(matched).round(2);
+                let cost_basis = matched.cost_basis.round(2);
+                let (code, reported_basis) = match reported.as_mut() {
+                    Some(left) => {
+                        let share = match n == last {
+                            true => *left,
+                            false => (*left * matched.amount)
+                                .checked_div(gain.amount)
+                                .unwrap_or(Amount::ZERO)
+                                .round(2),
+                        };
+                        *left -= share;
+                        ("B", share.round(2))
+                    }
+                    None => ("", cost_basis),
+                };
+                let adjustment = reported_basis - cost_basis;
+                lines.push(Line {
+                    part: category.part(),
+                    category,
+                    description: format!("{} {}", matched.amount, gain.asset),
+                    date_acquired: matched.acquired.date_naive(),
+                    date_sold: gain.timestamp.date_naive(),
+                    proceeds,
+                    cost_basis: reported_basis,
+                    code: code.to_string(),
+                    adjustment,
+                    gain: proceeds - reported_basis + adjustment,
+                });
+            }
+        }
+        lines.sort_by_key(|line| (line.category, line.date_sold, line.date_acquired));
+        Self { year, lines }
+    }
+
+    /// Totals of each box on its line of Schedule D, followed by the net short term (7) and
+    /// long term (15) totals
+    pub fn schedule_d(&self) -> Vec<Total> {
+        let mut boxes = BTreeMap::new();
+        let mut short = Total::new("7");
+        let mut long = Total::new("15");
+        for line in self.lines.iter() {
+            boxes
+                .entry(line.category)
+                .or_insert_with(|| Total::new(line.category.line()))
+                .add(line);
+            match line.part {
+                Part::I => short.add(line),
+                Part::II => long.add(line),
+            }
+        }
+        let (short_boxes, long_boxes): (Vec<_>, Vec<_>) = boxes
+            .into_iter()
+            .partition(|(category, _)| category.part() == Part::I);
+        short_boxes
+            .into_iter()
+            .map(|(_, total)| total)
+            .chain([short])
+            .chain(long_boxes.into_iter().map(|(_, total)| total))
+            .chain([long])
+            .collect()
+    }
+
+    pub fn write_csv<W>(&self, writer: W) -> Result<(), csv::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for line in self.lines.iter() {
+            writer.serialize(line)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_schedule_d_csv<W>(&self, writer: W) -> Result<(), csv::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for total in self.schedule_d().iter() {
+            writer.serialize(total)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the lines starting at `row`, followed by the Schedule D totals after an empty row,
+    /// returning the last row written
+    #[cfg(feature = "xlsx")]
+    pub fn write_xlsx(
+        &self,
+        worksheet: &mut rust_xlsxwriter::Worksheet,
+        mut row: rust_xlsxwriter::RowNum,
+    ) -> Result<rust_xlsxwriter::RowNum, rust_xlsxwriter::XlsxError> {
+        use rust_xlsxwriter::{ColNum, Format};
+        let bold = Format::new().set_bold();
+        let dollars = Format::new().set_num_format("#,##0.00");
+        let headers = |worksheet: &mut rust_xlsxwriter::Worksheet, row, headers: &[&str]| {
+            for (col, header) in headers.iter().enumerate() {
+                worksheet.write_with_format(row, col as ColNum, *header, &bold)?;
+            }
+            Ok::<_, rust_xlsxwriter::XlsxError>(())
+        };
+
+        headers(worksheet, row, &Line::HEADERS)?;
+        for line in self.lines.iter() {
+            row += 1;
+            worksheet.write(row, 0, line.part.to_string())?;
+            worksheet.write(row, 1, line.category.to_string())?;
+            worksheet.write(row, 2, &line.description)?;
+            worksheet.write(row, 3, line.date_acquired.format("%m/%d/%Y").to_string())?;
+            worksheet.write(row, 4, line.date_sold.format("%m/%d/%Y").to_string())?;
+            worksheet.write_with_format(row, 5, line.proceeds.to_f64(), &dollars)?;
+            worksheet.write_with_format(row, 6, line.cost_basis.to_f64(), &dollars)?;
+            worksheet.write(row, 7, &line.code)?;
+            worksheet.write_with_format(row, 8, line.adjustment.to_f64(), &dollars)?;
+            worksheet.write_with_format(row, 9, line.gain.to_f64(), &dollars)?;
+        }
+
+        row += 2;
+        worksheet.write_with_format(row, 0, format!("Schedule D {}", self.year), &bold)?;
+        row += 1;
+        headers(worksheet, row, &Total::HEADERS)?;
+        for total in self.schedule_d().iter() {
+            row += 1;
+            worksheet.write(row, 0, total.line)?;
+            worksheet.write_with_format(row, 1, total.proceeds.to_f64(), &dollars)?;
+            worksheet.write_with_format(row, 2, total.cost_basis.to_f64(), &dollars)?;
+            worksheet.write_with_format(row, 3, total.adjustment.to_f64(), &dollars)?;
+            worksheet.write_with_format(row, 4, total.gain.to_f64(), &dollars)?;
+        }
+        Ok(row)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Category, Form8949, Part, Reporting};
+    use crate::{
+        basis::{Gain, Matched},
+        sheet::{AccountingMethod, Output},
+        Amount,
+    };
+    use chrono::{DateTime, TimeZone, Utc};
+    use indoc::indoc;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn should_export_form_8949() {
+        let lot = |lot, acquired, vol, cost_basis| Matched {
+            lot,
+            acquired,
+            amount: amount(vol),
+            cost_basis: amount(cost_basis),
+        };
+        let gain = |unique_id, timestamp, vol, proceeds, lots: Vec<Matched<'static>>| Gain {
+            unique_id,
+            timestamp,
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            method: AccountingMethod::Fifo,
+            amount: amount(vol),
+            proceeds: amount(proceeds),
+            cost_basis: lots.iter().map(|m| m.cost_basis).sum(),
+            lots,
+        };
+        let gains = [
+            gain(
+                "s1",
+                date(2022, 6, 1),
+                "0.3",
+                "1000",
+                vec![
+                    lot("b1", date(2021, 1, 1), "0.1", "100.004"),
+                    lot("b2", date(2022, 1, 1), "0.2", "300"),
+                ],
+            ),
+            gain(
+                "s2",
+                date(2023, 1, 1),
+                "1",
+                "600",
+                vec![lot("b3", date(2021, 1, 1), "1", "100")],
+            ),
+            Gain {
+                unique_id: "s3",
+                timestamp: date(2022, 7, 1),
+                asset: "BTC",
+                exchange: "Coinbase",
+                holder: "Bob",
+                typ: Output::Sell,
+                method: AccountingMethod::Fifo,
+                amount: amount("1"),
+                proceeds: amount("500"),
+                cost_basis: amount("400"),
+                lots: vec![
+                    lot("b4", date(2022, 2, 1), "0.5", "150"),
+                    lot("b5", date(2022, 3, 1), "0.5", "250"),
+                ],
+            },
+            Gain {
+                typ: Output::Gift,
+                ..gain(
+                    "g1",
+                    date(2022, 8, 1),
+                    "1",
+                    "0",
+                    vec![lot("b6", date(2021, 1, 1), "1", "100")],
+                )
+            },
+        ];
+        assert_eq!(
+            Category::E,
+            Category::new(crate::report::Term::Long, Reporting::BasisNotReported)
+        );
+
+        let form = Form8949::new(&gains[..2], 2022, |_| Reporting::default());
+        assert_eq!(2, form.lines.len());
+        assert_eq!(
+            (Part::I, Category::C),
+            (form.lines[0].part, form.lines[0].category)
+        );
+        assert_eq!(amount("666.67"), form.lines[0].proceeds);
+
+        let mut csv = Vec::new();
+        form.write_csv(&mut csv).unwrap();
+        let expect = indoc! {"
+            part,box,description,date_acquired,date_sold,proceeds,cost_basis,code,adjustment,gain
+            I,C,0.2 BTC,01/01/2022,06/01/2022,666.67,300,,0,366.67
+            II,F,0.1 BTC,01/01/2021,06/01/2022,333.33,100,,0,233.33
+        "};
+        assert_eq!(expect, String::from_utf8(csv).unwrap());
+
+        let mut csv = Vec::new();
+        form.write_schedule_d_csv(&mut csv).unwrap();
+        let expect = indoc! {"
+            line,proceeds,cost_basis,adjustment,gain
+            3,666.67,300,0,366.67
+            7,666.67,300,0,366.67
+            10,333.33,100,0,233.33
+            15,333.33,100,0,233.33
+        "};
+        assert_eq!(expect, String::from_utf8(csv).unwrap());
+
+        // Coinbase reported a cost basis of 450 for s3, corrected on its lines with code B
+        let form = Form8949::new(&gains, 2022, |gain| match gain.exchange {
+            "Coinbase" => Reporting::BasisReported(amount("450")),
+            _ => Reporting::NotReported,
+        });
+        let categories: Vec<_> = form.lines.iter().map(|line| line.category).collect();
+        assert_eq!(
+            vec![Category::A, Category::A, Category::C, Category::F],
+            categories
+        );
+        let corrected: Vec<_> = form.lines[..2]
+            .iter()
+            .map(|line| {
+                (
+                    line.cost_basis,
+                    line.code.as_str(),
+                    line.adjustment,
+                    line.gain,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (amount("225"), "B", amount("75"), amount("100")),
+                (amount("225"), "B", amount("-25"), amount("0")),
+            ],
+            corrected
+        );
+        let total = &form.schedule_d()[0];
+        assert_eq!(("1b", amount("50")), (total.line, total.adjustment));
+    }
+}
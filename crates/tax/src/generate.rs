@@ -0,0 +1,162 @@
+/// generate.rs
+///
+/// Write an rp2 config for the assets, exchanges and holders of imported transactions
+use crate::{
+    sheet::{AccountingMethods, InputHeader, IntraHeader, OutputHeader},
+    Transaction,
+};
+use std::{collections::BTreeSet, io};
+
+#[derive(Debug, Default, Clone)]
+pub struct ConfigGenerator {
+    pub assets: BTreeSet<String>,
+    pub exchanges: BTreeSet<String>,
+    pub holders: BTreeSet<String>,
+    pub accounting_methods: Option<AccountingMethods>,
+}
+
+impl ConfigGenerator {
+    /// Discover the names used by the transactions, sorted
+    pub fn new<'a, I>(transactions: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        let mut generator = Self::default();
+        for tx in transactions {
+            generator.assets.insert(tx.asset().to_string());
+            match tx {
+                Transaction::In(tx) => {
+                    generator.exchanges.insert(tx.exchange.clone());
+                    generator.holders.insert(tx.holder.clone());
+                }
+                Transaction::Out(tx) => {
+                    generator.exchanges.insert(tx.exchange.clone());
+                    generator.holders.insert(tx.holder.clone());
+                }
+                Transaction::Intra(tx) => {
+                    generator.exchanges.insert(tx.from_exchange.clone());
+                    generator.exchanges.insert(tx.to_exchange.clone());
+                    generator.holders.insert(tx.from_holder.clone());
+                    generator.holders.insert(tx.to_holder.clone());
+                }
+            }
+        }
+        generator
+    }
+
+    /// Keep the accounting methods of a previous config
+    pub fn with_accounting_methods(mut self, methods: Option<&AccountingMethods>) -> Self {
+        self.accounting_methods = methods.cloned();
+        self
+    }
+
+    /// Write the config, with the columns of each table in the order of their fields
+    pub fn write<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let join = |names: &BTreeSet<String>| names.iter().cloned().collect::<Vec<_>>().join(", ");
+        writeln!(writer, "[general]")?;
+        writeln!(writer, "assets = {}", join(&self.assets))?;
+        writeln!(writer, "exchanges = {}", join(&self.exchanges))?;
+        writeln!(writer, "holders = {}", join(&self.holders))?;
+        for (name, fields) in [
+            ("in_header", InputHeader::FIELDS),
+            ("out_header", OutputHeader::FIELDS),
+            ("intra_header", IntraHeader::FIELDS),
+        ] {
+            writeln!(writer)?;
+            writeln!(writer, "[{name}]")?;
+            for (column, field) in fields.iter().enumerate() {
+                writeln!(writer, "{field} = {column}")?;
+            }
+        }
+        if let Some(methods) = self.accounting_methods.as_ref() {
+            let mut years: Vec<_> = methods.year.iter().collect();
+            years.sort_by_key(|(year, _)| **year);
+            writeln!(writer)?;
+            writeln!(writer, "[accounting_methods]")?;
+            for (year, method) in years {
+                writeln!(writer, "{year} = {method}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConfigGenerator;
+    use crate::{
+        sheet::{AccountingMethod, Config, Input, InputData, IntraData},
+        Amount, Transaction,
+    };
+    use chrono::{TimeZone, Utc};
+    use indoc::indoc;
+
+    #[test]
+    fn should_generate_config() {
+        let timestamp = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let transactions = [
+            Transaction::from(InputData {
+                timestamp,
+                asset: "ETH",
+                exchange: "Kraken",
+                holder: "Bob",
+                typ: Input::Buy,
+                spot_price: Amount::from(3000),
+                crypto_in: Amount::from(1),
+                crypto_fee: None,
+                fiat_in_no_fee: None,
+                fiat_in_with_fee: None,
+                fiat_fee: Amount::ZERO,
+                unique_id: "b1",
+                notes: None,
+            }),
+            Transaction::from(IntraData {
+                timestamp,
+                asset: "BTC",
+                from_exchange: "Kraken",
+                from_holder: "Bob",
+                to_exchange: "Coinbase",
+                to_holder: "Alice",
+                spot_price: None,
+                crypto_sent: Amount::from(1),
+                crypto_received: Amount::from(1),
+                unique_id: "t1",
+                notes: None,
+            }),
+        ];
+        let previous = dungeon_ini::from_str::<crate::sheet::AccountingMethods>(indoc! {"
+            2023 = hifo
+            2022 = lifo
+        "})
+        .unwrap();
+        let mut ini = Vec::new();
+        ConfigGenerator::new(&transactions)
+            .with_accounting_methods(Some(&previous))
+            .write(&mut ini)
+            .unwrap();
+        let ini = String::from_utf8(ini).unwrap();
+        assert!(ini.starts_with(indoc! {"
+            [general]
+            assets = BTC, ETH
+            exchanges = Coinbase, Kraken
+            holders = Alice, Bob
+
+            [in_header]
+            timestamp = 0
+            asset = 1
+        "}));
+        assert!(ini.ends_with(indoc! {"
+            [accounting_methods]
+            2022 = lifo
+            2023 = hifo
+        "}));
+
+        let config = dungeon_ini::from_str::<Config>(&ini).unwrap();
+        assert_eq!(Ok(()), config.validate());
+        assert_eq!("crypto_received", config.intra_header[8]);
+        assert_eq!(
+            AccountingMethod::Hifo,
+            config.accounting_methods.unwrap().get(2023)
+        );
+    }
+}
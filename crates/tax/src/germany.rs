@@ -0,0 +1,366 @@
+/// germany.rs
+///
+/// German private sales (§ 23 EStG): disposals are matched FIFO within the wallet they leave,
+/// and gains on coins held for more than one year are exempt. Taxable gains of a holder in a year
+/// are tax free below the Freigrenze, as is staking and lending income (§ 22 Nr. 3 EStG) below
+/// its own
+/// https://www.gesetze-im-internet.de/estg/__23.html
+use crate::{
+    basis::{self, Error, Gain, Ledger, Wallet},
+    report::Term,
+    sheet::{Input, InputData, IntraData, OutputData},
+    Amount,
+};
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Serialize, Serializer};
+use std::io;
+
+/// Yearly total of private sale gains below which they are tax free
+pub fn freigrenze(year: i32) -> Amount {
+    match year {
+        ..=2023 => Amount::from(600),
+        _ => Amount::from(1000),
+    }
+}
+
+/// Yearly total of staking and lending income below which it is tax free
+pub const INCOME_FREIGRENZE: i64 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Taxable,
+    /// Held for more than one year
+    Exempt,
+}
+
+impl Status {
+    pub fn new(acquired: DateTime<Utc>, disposed: DateTime<Utc>) -> Self {
+        match Term::new(acquired, disposed) {
+            Term::Short => Self::Taxable,
+            Term::Long => Self::Exempt,
+        }
+    }
+}
+
+/// Compute the realized gains of every disposal and transfer fee with FIFO per wallet, in
+/// chronological order, see [`basis::replay`]
+pub fn compute<'a>(
+    inputs: &[InputData<'a>],
+    outputs: &[OutputData<'a>],
+    intras: &[IntraData<'a>],
+) -> Result<Vec<Gain<'a>>, Error> {
+    let mut ledger: Ledger<Wallet> = Ledger::new(None);
+    basis::replay(
+        &mut ledger,
+        inputs,
+        outputs,
+        intras,
+        DateTime::<Utc>::MAX_UTC,
+    )
+}
+
+fn de_date<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(&date.format("%d.%m.%Y"))
+}
+
+/// A lot matched by a disposal, amounts in euros and cents
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Line {
+    pub asset: String,
+    pub exchange: String,
+    pub holder: String,
+    pub amount: Amount,
+    #[serde(serialize_with = "de_date")]
+    pub acquired: DateTime<Utc>,
+    #[serde(serialize_with = "de_date")]
+    pub disposed: DateTime<Utc>,
+    pub proceeds: Amount,
+    pub cost_basis: Amount,
+    pub gain: Amount,
+    pub status: Status,
+}
+
+impl Line {
+    pub const HEADERS: [&'static str; 10] = [
+        "asset",
+        "exchange",
+        "holder",
+        "amount",
+        "acquired",
+        "disposed",
+        "proceeds",
+        "cost_basis",
+        "gain",
+        "status",
+    ];
+}
+
+/// Totals of a holder in a tax year, where a total below its Freigrenze is not taxed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub holder: String,
+    pub year: i32,
+    pub taxable_gain: Amount,
+    pub exempt_gain: Amount,
+    pub freigrenze: Amount,
+    pub gain_taxed: bool,
+    pub income: Amount,
+    pub income_freigrenze: Amount,
+    pub income_taxed: bool,
+}
+
+/// Private sales of a holder in a tax year, in order of disposal, and their staking and lending
+/// income
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrivateSales {
+    pub holder: String,
+    pub year: i32,
+    pub lines: Vec<Line>,
+    pub income: Amount,
+}
+
+impl PrivateSales {
+    pub fn new(gains: &[Gain], inputs: &[InputData], holder: &str, year: i32) -> Self {
+        let mut lines = Vec::new();
+        let gains = gains.iter();
+        for gain in gains.filter(|gain| gain.holder == holder && gain.timestamp.year() == year) {
+            for matched in gain.lots.iter() {
+                let proceeds = gain.proceeds_of(matched).round(2);
+                let cost_basis = matched.cost_basis.round(2);
+                lines.push(Line {
+                    asset: gain.asset.to_string(),
+                    exchange: gain.exchange.to_string(),
+                    holder: gain.holder.to_string(),
+                    amount: matched.amount,
+                    acquired: matched.acquired,
+                    disposed: gain.timestamp,
+                    proceeds,
+                    cost_basis,
+                    gain: proceeds - cost_basis,
+                    status: Status::new(matched.acquired, gain.timestamp),
+                });
+            }
+        }
+        let income = inputs
+            .iter()
+            .filter(|input| input.holder == holder && input.timestamp.year() == year)
+            .filter(|input| matches!(input.typ, Input::Staking | Input::Interest))
+            .map(|input| {
+                input
+                    .fiat_in_no_fee
+                    .unwrap_or(input.crypto_in * input.spot_price)
+            })
+            .sum::<Amount>()
+            .round(2);
+        Self {
+            holder: holder.to_string(),
+            year,
+            lines,
+            income,
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        let total = |status| {
+            self.lines
+                .iter()
+                .filter(|line| line.status == status)
+                .map(|line| line.gain)
+                .sum::<Amount>()
+        };
+        let taxable_gain = total(Status::Taxable);
+        let freigrenze = freigrenze(self.year);
+        let income_freigrenze = Amount::from(INCOME_FREIGRENZE);
+        Summary {
+            holder: self.holder.clone(),
+            year: self.year,
+            taxable_gain,
+            exempt_gain: total(Status::Exempt),
+            freigrenze,
+            gain_taxed: taxable_gain >= freigrenze,
+            income: self.income,
+            income_freigrenze,
+            income_taxed: self.income >= income_freigrenze,
+        }
+    }
+
+    pub fn write_csv<W>(&self, writer: W) -> Result<(), csv::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for line in self.lines.iter() {
+            writer.serialize(line)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compute, PrivateSales, Status};
+    use crate::{
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        Amount,
+    };
+    use chrono::{DateTime, TimeZone, Utc};
+    use indoc::indoc;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+    }
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn buy<'a>(
+        unique_id: &'a str,
+        timestamp: DateTime<Utc>,
+        exchange: &'a str,
+        vol: &str,
+        price: &str,
+    ) -> InputData<'a> {
+        InputData {
+            timestamp,
+            asset: "BTC",
+            exchange,
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: amount(price),
+            crypto_in: amount(vol),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn sell<'a>(
+        unique_id: &'a str,
+        timestamp: DateTime<Utc>,
+        exchange: &'a str,
+        vol: &str,
+        price: &str,
+    ) -> OutputData<'a> {
+        OutputData {
+            timestamp,
+            asset: "BTC",
+            exchange,
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: amount(price),
+            crypto_out_no_fee: amount(vol),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: None,
+            fiat_fee: None,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn should_match_fifo_per_wallet() {
+        let inputs = [
+            buy("b1", date(2022, 1, 1), "Kraken", "1", "100"),
+            buy("b2", date(2022, 2, 1), "Coinbase", "1", "200"),
+        ];
+        // Coinbase sells its own lot, although Kraken holds an older one
+        let outputs = [sell("s1", date(2022, 3, 1), "Coinbase", "1", "300")];
+        let gains = compute(&inputs, &outputs, &[]).unwrap();
+        assert_eq!("b2", gains[0].lots[0].lot);
+        assert_eq!(amount("100"), gains[0].gain());
+    }
+
+    #[test]
+    fn should_keep_acquisition_date_on_transfer() {
+        let inputs = [buy("b1", date(2022, 1, 1), "Kraken", "2", "100")];
+        let intras = [IntraData {
+            timestamp: date(2022, 6, 1),
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Coinbase",
+            to_holder: "Bob",
+            spot_price: Some(amount("150")),
+            crypto_sent: amount("1.1"),
+            crypto_received: amount("1"),
+            unique_id: "t1",
+            notes: None,
+        }];
+        let outputs = [sell("s1", date(2023, 1, 2), "Coinbase", "1", "300")];
+        let gains = compute(&inputs, &outputs, &intras).unwrap();
+        assert_eq!(2, gains.len());
+        assert_eq!(Output::Fee, gains[0].typ);
+        assert_eq!(amount("5"), gains[0].gain());
+        assert_eq!(date(2022, 1, 1), gains[1].lots[0].acquired);
+        assert_eq!(
+            Status::Exempt,
+            Status::new(gains[1].lots[0].acquired, gains[1].timestamp)
+        );
+    }
+
+    #[test]
+    fn should_apply_freigrenze() {
+        let inputs = [
+            buy("b1", date(2022, 1, 1), "Kraken", "1", "1000"),
+            buy("b2", date(2023, 1, 2), "Kraken", "1", "1000"),
+        ];
+        let outputs = [
+            sell("s1", date(2023, 1, 2), "Kraken", "1", "2000"),
+            sell("s2", date(2023, 6, 1), "Kraken", "1", "1500"),
+        ];
+        let gains = compute(&inputs, &outputs, &[]).unwrap();
+        let sales = PrivateSales::new(&gains, &inputs, "Bob", 2023);
+        let summary = sales.summary();
+        assert_eq!(amount("500"), summary.taxable_gain);
+        assert_eq!(amount("1000"), summary.exempt_gain);
+        assert!(!summary.gain_taxed);
+
+        let mut csv = Vec::new();
+        sales.write_csv(&mut csv).unwrap();
+        let expect = indoc! {"
+            asset,exchange,holder,amount,acquired,disposed,proceeds,cost_basis,gain,status
+            BTC,Kraken,Bob,1,01.01.2022,02.01.2023,2000,1000,1000,exempt
+            BTC,Kraken,Bob,1,02.01.2023,01.06.2023,1500,1000,500,taxable
+        "};
+        assert_eq!(expect, String::from_utf8(csv).unwrap());
+
+        let outputs = [sell("s2", date(2023, 6, 1), "Kraken", "1", "1600")];
+        let gains = compute(&inputs[1..], &outputs, &[]).unwrap();
+        assert!(
+            PrivateSales::new(&gains, &inputs, "Bob", 2023)
+                .summary()
+                .gain_taxed
+        );
+    }
+
+    #[test]
+    fn should_apply_freigrenze_per_holder() {
+        let mut inputs = [
+            buy("b1", date(2023, 1, 2), "Kraken", "1", "1000"),
+            buy("b2", date(2023, 1, 2), "Kraken", "1", "1000"),
+        ];
+        let mut outputs = [
+            sell("s1", date(2023, 6, 1), "Kraken", "1", "1500"),
+            sell("s2", date(2023, 6, 1), "Kraken", "1", "1500"),
+        ];
+        inputs[1].holder = "Alice";
+        outputs[1].holder = "Alice";
+        let gains = compute(&inputs, &outputs, &[]).unwrap();
+        for holder in ["Alice", "Bob"] {
+            let summary = PrivateSales::new(&gains, &inputs, holder, 2023).summary();
+            assert_eq!(holder, summary.holder);
+            assert_eq!(amount("500"), summary.taxable_gain);
+            assert!(!summary.gain_taxed);
+        }
+    }
+}
@@ -1,5 +1,23 @@
+mod amount;
+pub mod basis;
+pub mod ca;
 pub mod date;
+pub mod form8949;
+pub mod generate;
+pub mod germany;
+#[cfg(feature = "ods")]
+pub mod ods;
 mod pair;
+pub mod price;
+#[cfg(any(feature = "ods", feature = "xlsx"))]
+pub mod reader;
+pub mod report;
 pub mod sheet;
+pub mod snapshot;
+pub mod transaction;
+pub mod uk;
+pub mod validate;
 
+pub use amount::{scale_of, Amount, ParseAmountError, MAX_SCALE};
 pub use pair::Pair;
+pub use transaction::Transaction;
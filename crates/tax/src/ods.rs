@@ -0,0 +1,310 @@
+/// ods.rs
+///
+/// Minimal OpenDocument spreadsheet writer, enough for LibreOffice and rp2 to open the tables
+/// https://docs.oasis-open.org/office/OpenDocument/v1.3/os/part3-schema/OpenDocument-v1.3-os-part3-schema.html
+use crate::{
+    sheet::{ColNum, Grid, RowNum},
+    Amount,
+};
+use quick_xml::{
+    events::{BytesDecl, BytesText, Event},
+    Writer,
+};
+use std::{
+    convert::Infallible,
+    fs,
+    io::{self, Seek, Write},
+    path::Path,
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+const NS_OFFICE: &str = "urn:oasis:names:tc:opendocument:xmlns:office:1.0";
+const NS_TABLE: &str = "urn:oasis:names:tc:opendocument:xmlns:table:1.0";
+const NS_TEXT: &str = "urn:oasis:names:tc:opendocument:xmlns:text:1.0";
+const NS_MANIFEST: &str = "urn:oasis:names:tc:opendocument:xmlns:manifest:1.0";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error {0}")]
+    Io(#[from] io::Error),
+    #[error("Zip error {0}")]
+    Zip(#[from] zip::result::ZipError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Empty,
+    String(String),
+    Number(Amount),
+}
+
+/// A sheet held in memory until the workbook is saved
+#[derive(Debug, Default)]
+pub struct Worksheet {
+    name: String,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Worksheet {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cell(&self, row: RowNum, col: ColNum) -> Option<&Value> {
+        self.rows
+            .get(row as usize)
+            .and_then(|cells| cells.get(col as usize))
+            .filter(|value| **value != Value::Empty)
+    }
+
+    pub fn write(&mut self, row: RowNum, col: ColNum, value: Value) {
+        let (row, col) = (row as usize, col as usize);
+        if row >= self.rows.len() {
+            self.rows.resize_with(row + 1, Vec::new);
+        }
+        let cells = &mut self.rows[row];
+        if col >= cells.len() {
+            cells.resize(col + 1, Value::Empty);
+        }
+        cells[col] = value;
+    }
+
+    fn write_xml<W: Write>(&self, writer: &mut Writer<W>) -> io::Result<()> {
+        writer
+            .create_element("table:table")
+            .with_attribute(("table:name", self.name.as_str()))
+            .write_inner_content(|writer| {
+                for cells in self.rows.iter() {
+                    writer
+                        .create_element("table:table-row")
+                        .write_inner_content(|writer| {
+                            if cells.is_empty() {
+                                writer.create_element("table:table-cell").write_empty()?;
+                            }
+                            for value in cells.iter() {
+                                write_cell(writer, value)?;
+                            }
+                            Ok(())
+                        })?;
+                }
+                Ok(())
+            })?;
+        Ok(())
+    }
+}
+
+impl Grid for Worksheet {
+    type Error = Infallible;
+    fn write_string(&mut self, row: RowNum, col: ColNum, value: &str) -> Result<(), Self::Error> {
+        self.write(row, col, Value::String(value.to_string()));
+        Ok(())
+    }
+
+    fn write_number(&mut self, row: RowNum, col: ColNum, value: Amount) -> Result<(), Self::Error> {
+        self.write(row, col, Value::Number(value));
+        Ok(())
+    }
+}
+
+fn write_cell<W: Write>(writer: &mut Writer<W>, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Empty => {
+            writer.create_element("table:table-cell").write_empty()?;
+        }
+        Value::String(s) => {
+            writer
+                .create_element("table:table-cell")
+                .with_attribute(("office:value-type", "string"))
+                .write_inner_content(|writer| {
+                    writer
+                        .create_element("text:p")
+                        .write_text_content(BytesText::new(s))?;
+                    Ok(())
+                })?;
+        }
+        Value::Number(n) => {
+            let n = n.to_string();
+            writer
+                .create_element("table:table-cell")
+                .with_attribute(("office:value-type", "float"))
+                .with_attribute(("office:value", n.as_str()))
+                .write_inner_content(|writer| {
+                    writer
+                        .create_element("text:p")
+                        .write_text_content(BytesText::new(&n))?;
+                    Ok(())
+                })?;
+        }
+    }
+    Ok(())
+}
+
+/// Workbook of one or more sheets, saved as a `.ods` file
+#[derive(Debug, Default)]
+pub struct Workbook {
+    sheets: Vec<Worksheet>,
+}
+
+impl Workbook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_worksheet(&mut self, name: &str) -> &mut Worksheet {
+        self.sheets.push(Worksheet {
+            name: name.to_string(),
+            rows: Vec::new(),
+        });
+        self.sheets.last_mut().expect("sheet was just added")
+    }
+
+    pub fn worksheets(&self) -> &[Worksheet] {
+        &self.sheets
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = fs::File::create(path)?;
+        self.save_to_writer(file)?;
+        Ok(())
+    }
+
+    /// The mimetype must be the first entry of the archive, and stored uncompressed
+    pub fn save_to_writer<W: Write + Seek>(&self, writer: W) -> Result<W, Error> {
+        let mut zip = ZipWriter::new(writer);
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(MIMETYPE.as_bytes())?;
+        zip.start_file("META-INF/manifest.xml", deflated)?;
+        write_manifest(&mut zip)?;
+        zip.start_file("content.xml", deflated)?;
+        self.write_content(&mut zip)?;
+        Ok(zip.finish()?)
+    }
+
+    fn write_content<W: Write>(&self, inner: W) -> io::Result<()> {
+        let mut writer = Writer::new(inner);
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+        writer
+            .create_element("office:document-content")
+            .with_attribute(("xmlns:office", NS_OFFICE))
+            .with_attribute(("xmlns:table", NS_TABLE))
+            .with_attribute(("xmlns:text", NS_TEXT))
+            .with_attribute(("office:version", "1.2"))
+            .write_inner_content(|writer| {
+                writer
+                    .create_element("office:body")
+                    .write_inner_content(|writer| {
+                        writer
+                            .create_element("office:spreadsheet")
+                            .write_inner_content(|writer| {
+                                for sheet in self.sheets.iter() {
+                                    sheet.write_xml(writer)?;
+                                }
+                                Ok(())
+                            })?;
+                        Ok(())
+                    })?;
+                Ok(())
+            })?;
+        Ok(())
+    }
+}
+
+fn write_manifest<W: Write>(inner: W) -> io::Result<()> {
+    let mut writer = Writer::new(inner);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer
+        .create_element("manifest:manifest")
+        .with_attribute(("xmlns:manifest", NS_MANIFEST))
+        .with_attribute(("manifest:version", "1.2"))
+        .write_inner_content(|writer| {
+            writer
+                .create_element("manifest:file-entry")
+                .with_attribute(("manifest:full-path", "/"))
+                .with_attribute(("manifest:version", "1.2"))
+                .with_attribute(("manifest:media-type", MIMETYPE))
+                .write_empty()?;
+            writer
+                .create_element("manifest:file-entry")
+                .with_attribute(("manifest:full-path", "content.xml"))
+                .with_attribute(("manifest:media-type", "text/xml"))
+                .write_empty()?;
+            Ok(())
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Value, Workbook, MIMETYPE};
+    use crate::sheet::{Input, InputData, OutputData};
+    use crate::Amount;
+    use chrono::{TimeZone, Utc};
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn should_write_tables() {
+        let headers: Vec<String> = ["timestamp", "asset", "", "transaction_type", "crypto_in"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .serialize(InputData {
+                timestamp: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+                asset: "BTC",
+                exchange: "Kraken",
+                holder: "Bob",
+                typ: Input::Buy,
+                spot_price: Amount::from(40000),
+                crypto_in: "0.12345678".parse().unwrap(),
+                crypto_fee: None,
+                fiat_in_no_fee: None,
+                fiat_in_with_fee: None,
+                fiat_fee: Amount::ZERO,
+                unique_id: "b1",
+                notes: None,
+            })
+            .unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet("BTC");
+        let row = InputData::write_table(sheet, 0, &headers, &data).unwrap();
+        let row = OutputData::write_table(sheet, row + 2, &headers, &[]).unwrap();
+        assert_eq!(7, row);
+        let string = |s: &str| Some(Value::String(s.to_string()));
+        assert_eq!(string("IN").as_ref(), sheet.cell(0, 0));
+        assert_eq!(string("crypto_in").as_ref(), sheet.cell(1, 4));
+        assert_eq!(string("BTC").as_ref(), sheet.cell(2, 1));
+        assert_eq!(None, sheet.cell(2, 2));
+        assert_eq!(string("buy").as_ref(), sheet.cell(2, 3));
+        assert_eq!(
+            Some(&Value::Number("0.12345678".parse().unwrap())),
+            sheet.cell(2, 4)
+        );
+        assert_eq!(string("TABLE END").as_ref(), sheet.cell(3, 0));
+        assert_eq!(string("OUT").as_ref(), sheet.cell(5, 0));
+        assert_eq!(string("TABLE END").as_ref(), sheet.cell(7, 0));
+
+        let ods = workbook.save_to_writer(Cursor::new(Vec::new())).unwrap();
+        let mut zip = zip::ZipArchive::new(ods).unwrap();
+        let mut mimetype = String::new();
+        zip.by_index(0)
+            .unwrap()
+            .read_to_string(&mut mimetype)
+            .unwrap();
+        assert_eq!(MIMETYPE, mimetype);
+        let mut content = String::new();
+        zip.by_name("content.xml")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert!(content.contains(r#"<table:table table:name="BTC">"#));
+        assert!(content.contains(
+            r#"<table:table-cell office:value-type="float" office:value="0.12345678"><text:p>0.12345678</text:p></table:table-cell>"#
+        ));
+    }
+}
@@ -0,0 +1,447 @@
+/// price.rs
+///
+/// Historical prices for rows without a reliable spot price, looked up in local OHLC candles
+use crate::{
+    sheet::{InputData, IntraData, OutputData},
+    validate::Table,
+    Amount,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::{collections::HashMap, fs, io, path::Path};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error {0}")]
+    Io(#[from] io::Error),
+    #[error("CSV error {0}")]
+    Csv(#[from] csv::Error),
+    #[error("{0}:{1}: invalid candle")]
+    Candle(String, u64),
+}
+
+/// The price of one unit of an asset in a quote currency at a point in time
+pub trait PriceOracle {
+    fn price(&self, base: &str, quote: &str, at: DateTime<Utc>) -> Option<Amount>;
+}
+
+impl<T: PriceOracle + ?Sized> PriceOracle for &T {
+    fn price(&self, base: &str, quote: &str, at: DateTime<Utc>) -> Option<Amount> {
+        (**self).price(base, quote, at)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    /// Start of the interval
+    pub timestamp: DateTime<Utc>,
+    pub open: Amount,
+    pub high: Amount,
+    pub low: Amount,
+    pub close: Amount,
+}
+
+impl Candle {
+    /// A row of a headerless OHLC export: unix seconds, open, high, low and close, followed by
+    /// optional columns such as the volume and count of trades
+    fn from_record(record: &csv::StringRecord) -> Option<Self> {
+        let amount = |i: usize| record.get(i)?.trim().parse::<Amount>().ok();
+        let seconds = record.get(0)?.trim().parse().ok()?;
+        Some(Self {
+            timestamp: DateTime::from_timestamp(seconds, 0)?,
+            open: amount(1)?,
+            high: amount(2)?,
+            low: amount(3)?,
+            close: amount(4)?,
+        })
+    }
+}
+
+/// Candles of every pair. A price is the open of the candle nearest to the time looked up,
+/// provided it starts within the tolerance. With interpolation, a time between two candles
+/// within the tolerance is priced on the line between their opens
+#[derive(Debug, Clone)]
+pub struct PriceStore {
+    candles: HashMap<(String, String), Vec<Candle>>,
+    tolerance: Duration,
+    interpolate: bool,
+}
+
+impl Default for PriceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceStore {
+    /// An empty store, with a tolerance of one day and no interpolation
+    pub fn new() -> Self {
+        Self {
+            candles: HashMap::new(),
+            tolerance: Duration::days(1),
+            interpolate: false,
+        }
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolate: bool) -> Self {
+        self.interpolate = interpolate;
+        self
+    }
+
+    /// Load every file of a directory named after its pair, IE: `BTC-USD.csv`. Other files are
+    /// ignored
+    pub fn open<P: AsRef<Path>>(mut self, dir: P) -> Result<Self, Error> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some((base, quote)) = pair_of(&path) else {
+                continue;
+            };
+            let name = path.display().to_string();
+            self.load(&base, &quote, &name, fs::File::open(&path)?)?;
+        }
+        Ok(self)
+    }
+
+    /// Add the candles of a pair, where `name` locates errors
+    pub fn load<R: io::Read>(
+        &mut self,
+        base: &str,
+        quote: &str,
+        name: &str,
+        reader: R,
+    ) -> Result<(), Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(reader);
+        let candles = self
+            .candles
+            .entry((base.to_string(), quote.to_string()))
+            .or_default();
+        let mut record = csv::StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let line = record.position().map_or(0, |p| p.line());
+            let candle = Candle::from_record(&record)
+                .ok_or_else(|| Error::Candle(name.to_string(), line))?;
+            candles.push(candle);
+        }
+        candles.sort_by_key(|candle| candle.timestamp);
+        candles.dedup_by_key(|candle| candle.timestamp);
+        Ok(())
+    }
+
+    /// Candles of a pair in time order
+    pub fn candles(&self, base: &str, quote: &str) -> &[Candle] {
+        self.candles
+            .get(&(base.to_string(), quote.to_string()))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Pair of a file named `BASE-QUOTE.csv`
+fn pair_of(path: &Path) -> Option<(String, String)> {
+    if path.extension()? != "csv" {
+        return None;
+    }
+    let (base, quote) = path.file_stem()?.to_str()?.split_once('-')?;
+    Some((base.to_string(), quote.to_string()))
+}
+
+impl PriceOracle for PriceStore {
+    fn price(&self, base: &str, quote: &str, at: DateTime<Utc>) -> Option<Amount> {
+        let candles = self.candles(base, quote);
+        let next = candles.partition_point(|candle| candle.timestamp < at);
+        let after = candles
+            .get(next)
+            .filter(|c| c.timestamp - at <= self.tolerance);
+        if let Some(candle) = after.filter(|c| c.timestamp == at) {
+            return Some(candle.open);
+        }
+        let before = next
+            .checked_sub(1)
+            .and_then(|i| candles.get(i))
+            .filter(|c| at - c.timestamp <= self.tolerance);
+        match (before, after) {
+            (Some(before), Some(after)) if self.interpolate => {
+                let elapsed = Amount::from((at - before.timestamp).num_seconds());
+                let span = Amount::from((after.timestamp - before.timestamp).num_seconds());
+                let slope = (after.open - before.open).checked_mul(elapsed)?;
+                Some(before.open + slope.checked_div(span)?)
+            }
+            (Some(before), Some(after)) => match at - before.timestamp <= after.timestamp - at {
+                true => Some(before.open),
+                false => Some(after.open),
+            },
+            (Some(candle), None) | (None, Some(candle)) => Some(candle.open),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Price an IN row with the oracle, for rows whose own price is not reliable. The fiat amounts
+/// derived from the replaced price are cleared, so they are derived from the new spot price, and
+/// the fiat fee is converted at the new price. Returns false when the oracle has no price, or
+/// when a fee has no price to be converted from, leaving the row as is
+pub fn reprice_input<O: PriceOracle>(oracle: &O, quote: &str, data: &mut InputData<'_>) -> bool {
+    let Some(price) = oracle.price(data.asset, quote, data.timestamp) else {
+        return false;
+    };
+    let Some(fiat_fee) = convert(data.fiat_fee, data.spot_price, price) else {
+        return false;
+    };
+    data.spot_price = price;
+    data.fiat_in_no_fee = None;
+    data.fiat_in_with_fee = None;
+    data.fiat_fee = fiat_fee;
+    true
+}
+
+/// Price an OUT row with the oracle like [`reprice_input`]. A fiat fee is cleared when it is
+/// derived from the crypto fee
+pub fn reprice_output<O: PriceOracle>(oracle: &O, quote: &str, data: &mut OutputData<'_>) -> bool {
+    let Some(price) = oracle.price(data.asset, quote, data.timestamp) else {
+        return false;
+    };
+    let fiat_fee = match (data.crypto_fee.is_zero(), data.fiat_fee) {
+        (true, Some(fee)) => match convert(fee, data.spot_price, price) {
+            Some(fee) => Some(fee),
+            None => return false,
+        },
+        _ => None,
+    };
+    data.spot_price = price;
+    data.fiat_out_no_fee = None;
+    data.fiat_fee = fiat_fee;
+    true
+}
+
+/// Convert a fiat amount valued at the price `from` to the price `to`
+fn convert(amount: Amount, from: Amount, to: Amount) -> Option<Amount> {
+    match amount.is_zero() {
+        true => Some(amount),
+        false => amount.checked_mul(to)?.checked_div(from),
+    }
+}
+
+/// Price an IN row without a spot price. Returns false when it is still missing
+pub fn fill_input<O: PriceOracle>(oracle: &O, quote: &str, data: &mut InputData<'_>) -> bool {
+    !data.spot_price.is_zero() || reprice_input(oracle, quote, data)
+}
+
+/// Price an OUT row without a spot price. Returns false when it is still missing
+pub fn fill_output<O: PriceOracle>(oracle: &O, quote: &str, data: &mut OutputData<'_>) -> bool {
+    !data.spot_price.is_zero() || reprice_output(oracle, quote, data)
+}
+
+/// Price an INTRA row without a spot price. Returns false when it is still missing
+pub fn fill_intra<O: PriceOracle>(oracle: &O, quote: &str, data: &mut IntraData<'_>) -> bool {
+    if data.spot_price.is_none() {
+        data.spot_price = oracle.price(data.asset, quote, data.timestamp);
+    }
+    data.spot_price.is_some()
+}
+
+/// Price the rows without a spot price, returning the table and index of the rows which are
+/// still missing one
+pub fn fill<O: PriceOracle>(
+    oracle: &O,
+    quote: &str,
+    inputs: &mut [InputData<'_>],
+    outputs: &mut [OutputData<'_>],
+    intras: &mut [IntraData<'_>],
+) -> Vec<(Table, usize)> {
+    let mut missing = Vec::new();
+    for (row, data) in inputs.iter_mut().enumerate() {
+        if !fill_input(oracle, quote, data) {
+            missing.push((Table::In, row));
+        }
+    }
+    for (row, data) in outputs.iter_mut().enumerate() {
+        if !fill_output(oracle, quote, data) {
+            missing.push((Table::Out, row));
+        }
+    }
+    for (row, data) in intras.iter_mut().enumerate() {
+        if !fill_intra(oracle, quote, data) {
+            missing.push((Table::Intra, row));
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        fill, fill_input, fill_output, reprice_input, reprice_output, Error, PriceOracle,
+        PriceStore,
+    };
+    use crate::{
+        basis::Lot,
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        validate::Table,
+        Amount,
+    };
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use indoc::indoc;
+    use std::fs;
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn time(hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, 1, 1, hour, min, 0).unwrap()
+    }
+
+    /// Hourly candles from 2022-01-01 00:00 UTC, with the volume and count of trades
+    const CANDLES: &str = indoc! {"
+        1640995200,100,110,90,105,12.5,40
+        1640998800,200,210,190,205,3.1,12
+        1641002400,300,310,290,305,7,20
+    "};
+
+    fn store() -> PriceStore {
+        let mut store = PriceStore::new().with_tolerance(Duration::minutes(90));
+        store
+            .load("BTC", "USD", "BTC-USD.csv", CANDLES.as_bytes())
+            .unwrap();
+        store
+    }
+
+    #[test]
+    fn should_find_nearest_candle() {
+        let store = store();
+        assert_eq!(Some(amount("200")), store.price("BTC", "USD", time(1, 0)));
+        assert_eq!(Some(amount("100")), store.price("BTC", "USD", time(0, 20)));
+        assert_eq!(Some(amount("200")), store.price("BTC", "USD", time(0, 40)));
+        assert_eq!(Some(amount("300")), store.price("BTC", "USD", time(3, 30)));
+        assert_eq!(None, store.price("BTC", "USD", time(3, 31)));
+        assert_eq!(None, store.price("ETH", "USD", time(1, 0)));
+    }
+
+    #[test]
+    fn should_interpolate() {
+        let store = store().with_interpolation(true);
+        assert_eq!(Some(amount("125")), store.price("BTC", "USD", time(0, 15)));
+        assert_eq!(Some(amount("250")), store.price("BTC", "USD", time(1, 30)));
+        assert_eq!(Some(amount("300")), store.price("BTC", "USD", time(2, 0)));
+    }
+
+    #[test]
+    fn should_open_directory() {
+        let dir = std::env::temp_dir().join(format!("dungeon-prices-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("BTC-USD.csv"), CANDLES).unwrap();
+        fs::write(dir.join("ETH-USD.csv"), "1640995200,10,11,9\n").unwrap();
+        match PriceStore::new().open(&dir) {
+            Err(Error::Candle(name, line)) => {
+                assert!(name.ends_with("ETH-USD.csv"));
+                assert_eq!(1, line);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        fs::remove_file(dir.join("ETH-USD.csv")).unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+        let store = PriceStore::new().open(&dir).unwrap();
+        assert_eq!(3, store.candles("BTC", "USD").len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_fill_missing_prices() {
+        let store = store();
+        let mut inputs = [InputData {
+            timestamp: time(1, 10),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Airdrop,
+            spot_price: Amount::ZERO,
+            crypto_in: amount("1"),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id: "a1",
+            notes: None,
+        }];
+        let intra = |unique_id, timestamp| IntraData {
+            timestamp,
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Coinbase",
+            to_holder: "Bob",
+            spot_price: None,
+            crypto_sent: amount("1"),
+            crypto_received: amount("0.9"),
+            unique_id,
+            notes: None,
+        };
+        let mut intras = [intra("t1", time(2, 0)), intra("t2", time(12, 0))];
+        let missing = fill(&store, "USD", &mut inputs, &mut [], &mut intras);
+        assert_eq!(amount("200"), inputs[0].spot_price);
+        assert_eq!(Some(amount("300")), intras[0].spot_price);
+        assert_eq!(vec![(Table::Intra, 1)], missing);
+    }
+
+    #[test]
+    fn should_clear_derived_fiat_amounts() {
+        let store = store();
+        let mut output = OutputData {
+            timestamp: time(1, 10),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: Amount::ZERO,
+            crypto_out_no_fee: amount("2"),
+            crypto_fee: amount("0.01"),
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: Some(amount("123")),
+            fiat_fee: Some(amount("1")),
+            unique_id: "s1",
+            notes: None,
+        };
+        assert!(fill_output(&store, "USD", &mut output));
+        assert_eq!(amount("200"), output.spot_price);
+        assert_eq!((None, None), (output.fiat_out_no_fee, output.fiat_fee));
+
+        // A fee paid in fiat is kept, converted at the new price
+        output.spot_price = amount("100");
+        output.crypto_fee = Amount::ZERO;
+        output.fiat_fee = Some(amount("1"));
+        assert!(reprice_output(&store, "USD", &mut output));
+        assert_eq!(Some(amount("2")), output.fiat_fee);
+        output.spot_price = Amount::ZERO;
+        assert!(!fill_output(&store, "USD", &mut output));
+        assert_eq!(Some(amount("2")), output.fiat_fee);
+
+        // A price derived from another trade is replaced, and the cost basis follows it
+        let mut input = InputData {
+            timestamp: time(1, 10),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: amount("500"),
+            crypto_in: amount("2"),
+            crypto_fee: None,
+            fiat_in_no_fee: Some(amount("1000")),
+            fiat_in_with_fee: Some(amount("1005")),
+            fiat_fee: amount("5"),
+            unique_id: "b1",
+            notes: None,
+        };
+        assert!(fill_input(&store, "USD", &mut input));
+        assert_eq!(amount("500"), input.spot_price);
+        assert!(reprice_input(&store, "USD", &mut input));
+        assert_eq!(amount("2"), input.fiat_fee);
+        assert_eq!(amount("402"), Lot::from(&input).cost_basis);
+        assert!(!reprice_input(&store, "EUR", &mut input));
+    }
+}
@@ -0,0 +1,896 @@
+/// reader.rs
+///
+/// Read the IN, OUT and INTRA tables of rp2 input workbooks (.ods or .xlsx)
+use crate::sheet::{ColNum, Config, InputData, IntraData, OutputData, RowNum};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use serde::de::{
+    self,
+    value::{BorrowedStrDeserializer, Error as ValueError},
+    Deserialize, IntoDeserializer,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    io::{self, BufReader, Read, Seek},
+    path::Path,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error {0}")]
+    Io(#[from] io::Error),
+    #[error("Zip error {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("XML error {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("Not an ods or xlsx workbook")]
+    Format,
+    #[error("{0}: {1} table without TABLE END")]
+    Unterminated(Location, &'static str),
+    #[error("{0}: {1}")]
+    Cell(Location, String),
+}
+
+/// Sheet and cell of an error, IE: `BTC!C5`. The column is unknown for missing fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub sheet: String,
+    pub row: RowNum,
+    pub col: Option<ColNum>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}!", self.sheet)?;
+        if let Some(col) = self.col {
+            let mut letters = Vec::new();
+            let mut n = col as u32 + 1;
+            while n > 0 {
+                letters.push(b'A' + ((n - 1) % 26) as u8);
+                n = (n - 1) / 26;
+            }
+            letters.reverse();
+            f.write_str(std::str::from_utf8(&letters).map_err(|_| fmt::Error)?)?;
+        }
+        write!(f, "{}", self.row + 1)
+    }
+}
+
+/// Rows of the tables of one sheet, borrowed from the cells of the sheet
+#[derive(Debug, Default)]
+pub struct Tables<'a> {
+    pub input: Vec<InputData<'a>>,
+    pub output: Vec<OutputData<'a>>,
+    pub intra: Vec<IntraData<'a>>,
+}
+
+/// Text of the non empty cells of a sheet
+#[derive(Debug, Default)]
+pub struct Sheet {
+    pub name: String,
+    rows: BTreeMap<RowNum, BTreeMap<ColNum, String>>,
+}
+
+impl Sheet {
+    pub fn cell(&self, row: RowNum, col: ColNum) -> Option<&str> {
+        self.rows
+            .get(&row)
+            .and_then(|cells| cells.get(&col))
+            .map(String::as_str)
+    }
+
+    /// Deserialize the rows between the table name and the `TABLE END` marker of each table,
+    /// skipping the header row. Columns are mapped through the headers of the config
+    pub fn tables(&self, config: &Config) -> Result<Tables<'_>, Error> {
+        let mut tables = Tables::default();
+        let mut table: Option<(&'static str, RowNum)> = None;
+        let mut header = false;
+        for (row, cells) in self.rows.iter() {
+            let first = cells.get(&0).map(String::as_str);
+            let Some((name, _)) = table else {
+                table = match first {
+                    Some("IN") => Some(("IN", *row)),
+                    Some("OUT") => Some(("OUT", *row)),
+                    Some("INTRA") => Some(("INTRA", *row)),
+                    _ => None,
+                };
+                header = table.is_some();
+                continue;
+            };
+            if header {
+                header = false;
+                continue;
+            }
+            match (first, name) {
+                (Some("TABLE END"), _) => table = None,
+                (Some("IN" | "OUT" | "INTRA"), _) => break,
+                (_, "IN") => {
+                    tables
+                        .input
+                        .push(self.deserialize(*row, cells, &config.in_header.0)?)
+                }
+                (_, "OUT") => {
+                    tables
+                        .output
+                        .push(self.deserialize(*row, cells, &config.out_header.0)?)
+                }
+                _ => tables
+                    .intra
+                    .push(self.deserialize(*row, cells, &config.intra_header.0)?),
+            }
+        }
+        match table {
+            None => Ok(tables),
+            Some((name, row)) => Err(Error::Unterminated(self.location(row, Some(0)), name)),
+        }
+    }
+
+    fn deserialize<'a, T>(
+        &'a self,
+        row: RowNum,
+        cells: &'a BTreeMap<ColNum, String>,
+        headers: &[String],
+    ) -> Result<T, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        let mut access = RowAccess {
+            cells,
+            headers,
+            next: 0,
+            current: None,
+        };
+        T::deserialize(de::value::MapAccessDeserializer::new(&mut access))
+            .map_err(|e| Error::Cell(self.location(row, access.current), e.to_string()))
+    }
+
+    fn location(&self, row: RowNum, col: Option<ColNum>) -> Location {
+        Location {
+            sheet: self.name.clone(),
+            row,
+            col,
+        }
+    }
+}
+
+/// Fields of a row, named by the header of their column
+struct RowAccess<'a, 'h> {
+    cells: &'a BTreeMap<ColNum, String>,
+    headers: &'h [String],
+    next: usize,
+    current: Option<ColNum>,
+}
+
+impl<'de> de::MapAccess<'de> for &mut RowAccess<'de, '_> {
+    type Error = ValueError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        while let Some(header) = self.headers.get(self.next) {
+            let col = self.next as ColNum;
+            self.next += 1;
+            if !header.is_empty() {
+                self.current = Some(col);
+                return seed
+                    .deserialize(header.as_str().into_deserializer())
+                    .map(Some);
+            }
+        }
+        self.current = None;
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let cell = self
+            .current
+            .and_then(|col| self.cells.get(&col))
+            .map_or("", String::as_str);
+        seed.deserialize(CellDeserializer(cell))
+    }
+}
+
+/// Text of a cell, where an empty cell is a missing value
+struct CellDeserializer<'de>(&'de str);
+
+impl<'de> de::Deserializer<'de> for CellDeserializer<'de> {
+    type Error = ValueError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.0.is_empty() {
+            true => visitor.visit_none(),
+            false => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_enum(BorrowedStrDeserializer::new(self.0))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Sheets of an rp2 input workbook
+#[derive(Debug, Default)]
+pub struct Workbook {
+    pub sheets: Vec<Sheet>,
+}
+
+impl Workbook {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(fs::File::open(path)?)
+    }
+
+    /// Read an ods or xlsx workbook, told apart by the entries of the archive
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Self, Error> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let sheets = match (
+            archive.index_for_name("content.xml"),
+            archive.index_for_name("xl/workbook.xml"),
+        ) {
+            (Some(_), _) => read_ods(&mut archive)?,
+            (None, Some(_)) => read_xlsx(&mut archive)?,
+            (None, None) => return Err(Error::Format),
+        };
+        Ok(Self { sheets })
+    }
+
+    pub fn sheet(&self, name: &str) -> Option<&Sheet> {
+        self.sheets.iter().find(|sheet| sheet.name == name)
+    }
+}
+
+type Archive<R> = zip::ZipArchive<R>;
+
+fn open_xml<'a, R: Read + Seek>(
+    archive: &'a mut Archive<R>,
+    name: &str,
+) -> Result<Reader<BufReader<zip::read::ZipFile<'a>>>, Error> {
+    let file = archive.by_name(name)?;
+    Ok(Reader::from_reader(BufReader::new(file)))
+}
+
+/// Value of an attribute by its local name, IE: `table:name` by `name`
+fn attribute(e: &BytesStart, name: &[u8]) -> Result<Option<String>, Error> {
+    for attr in e.attributes() {
+        let attr = attr.map_err(quick_xml::Error::from)?;
+        if attr.key.local_name().as_ref() == name {
+            return Ok(Some(attr.unescape_value()?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+fn repeated(e: &BytesStart, name: &[u8]) -> Result<u32, Error> {
+    Ok(attribute(e, name)?
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1))
+}
+
+/// A typed date, IE: `2022-01-01T12:00:00` or `2022-01-01`, in the format of the tables
+fn iso_date(value: &str) -> Option<String> {
+    let value = value.trim_end_matches('Z');
+    let date = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN))
+        })
+        .ok()?;
+    Some(crate::date::format(&date.and_utc()).to_string())
+}
+
+/// A date stored as a number of days since 1899-12-30, in the format of the tables
+fn serial_date(value: &str) -> Option<String> {
+    let days = value.parse::<f64>().ok()?;
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?.and_time(NaiveTime::MIN);
+    let date =
+        epoch.checked_add_signed(Duration::milliseconds((days * 86_400_000.0).round() as i64))?;
+    Some(crate::date::format(&date.and_utc()).to_string())
+}
+
+/// Built in number formats 14 to 22 and 45 to 47 are dates, custom formats are dates when their
+/// code has a date or time part outside of quoted text and brackets (colors, locales, ...)
+fn is_date_format(id: &str, custom: &HashMap<String, String>) -> bool {
+    if let Ok(14..=22 | 45..=47) = id.parse::<u32>() {
+        return true;
+    }
+    let Some(code) = custom.get(id) else {
+        return false;
+    };
+    let (mut quoted, mut bracket) = (false, false);
+    code.chars().any(|c| {
+        match c {
+            '"' => quoted = !quoted,
+            '[' if !quoted => bracket = true,
+            ']' if !quoted => bracket = false,
+            'y' | 'm' | 'd' | 'h' | 's' | 'Y' | 'M' | 'D' | 'H' | 'S' => {
+                return !quoted && !bracket
+            }
+            _ => {}
+        }
+        false
+    })
+}
+
+/// Cell being read from an ods sheet
+struct OdsCell {
+    value: Option<String>,
+    text: String,
+    repeat: u32,
+}
+
+impl OdsCell {
+    /// Typed values (float, date, ...) are read from their attribute, since the text of the cell
+    /// is formatted for display
+    fn new(e: &BytesStart) -> Result<Self, Error> {
+        let mut value = None;
+        for name in [
+            &b"value"[..],
+            b"date-value",
+            b"time-value",
+            b"boolean-value",
+        ] {
+            if let Some(v) = attribute(e, name)? {
+                value = Some(match name {
+                    b"date-value" => iso_date(&v).unwrap_or(v),
+                    _ => v,
+                });
+                break;
+            }
+        }
+        Ok(Self {
+            value,
+            text: String::new(),
+            repeat: repeated(e, b"number-columns-repeated")?,
+        })
+    }
+}
+
+fn read_ods<R: Read + Seek>(archive: &mut Archive<R>) -> Result<Vec<Sheet>, Error> {
+    let mut reader = open_xml(archive, "content.xml")?;
+    let mut buf = Vec::new();
+    let mut sheets: Vec<Sheet> = Vec::new();
+    let (mut row, mut col) = (0 as RowNum, 0u32);
+    let mut rows_repeat = 1;
+    let mut cells = BTreeMap::new();
+    let mut cell: Option<OdsCell> = None;
+    let mut annotation = 0;
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let empty = matches!(event, Event::Empty(_));
+                match e.local_name().as_ref() {
+                    b"table" if !empty => {
+                        let name = attribute(e, b"name")?.unwrap_or_default();
+                        sheets.push(Sheet {
+                            name,
+                            rows: BTreeMap::new(),
+                        });
+                        row = 0;
+                    }
+                    b"table-row" => {
+                        rows_repeat = repeated(e, b"number-rows-repeated")?;
+                        col = 0;
+                        if empty {
+                            row = row.saturating_add(rows_repeat);
+                        }
+                    }
+                    b"table-cell" | b"covered-table-cell" => {
+                        let new = OdsCell::new(e)?;
+                        match empty {
+                            true => col = col.saturating_add(new.repeat),
+                            false => cell = Some(new),
+                        }
+                    }
+                    b"annotation" if !empty => annotation += 1,
+                    b"p" if annotation == 0 => {
+                        if let Some(cell) = cell.as_mut().filter(|cell| !cell.text.is_empty()) {
+                            cell.text.push('\n');
+                        }
+                    }
+                    b"s" if annotation == 0 => {
+                        if let Some(cell) = cell.as_mut() {
+                            let n = repeated(e, b"c")?;
+                            cell.text.extend((0..n).map(|_| ' '));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(ref t) if annotation == 0 => {
+                if let Some(cell) = cell.as_mut() {
+                    cell.text.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(ref e) => match e.local_name().as_ref() {
+                b"table-cell" | b"covered-table-cell" => {
+                    if let Some(OdsCell {
+                        value,
+                        text,
+                        repeat,
+                    }) = cell.take()
+                    {
+                        let content = value.unwrap_or(text);
+                        if !content.is_empty() {
+                            for n in 0..repeat {
+                                if let Ok(col) = ColNum::try_from(col + n) {
+                                    cells.insert(col, content.clone());
+                                }
+                            }
+                        }
+                        col = col.saturating_add(repeat);
+                    }
+                }
+                b"table-row" => {
+                    if let Some(sheet) = sheets.last_mut().filter(|_| !cells.is_empty()) {
+                        for n in 0..rows_repeat {
+                            sheet.rows.insert(row + n, cells.clone());
+                        }
+                    }
+                    cells.clear();
+                    row = row.saturating_add(rows_repeat);
+                }
+                b"annotation" => annotation -= 1,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(sheets)
+}
+
+/// Row and column of a cell reference, IE: `C5`
+fn cell_reference(r: &str) -> Option<(RowNum, ColNum)> {
+    let split = r.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = r.split_at(split);
+    let col = letters.bytes().try_fold(0u32, |col, b| match b {
+        b'A'..=b'Z' => Some(col * 26 + (b - b'A') as u32 + 1),
+        _ => None,
+    })?;
+    let row = digits.parse::<RowNum>().ok()?;
+    Some((
+        row.checked_sub(1)?,
+        ColNum::try_from(col.checked_sub(1)?).ok()?,
+    ))
+}
+
+fn read_xlsx<R: Read + Seek>(archive: &mut Archive<R>) -> Result<Vec<Sheet>, Error> {
+    let mut buf = Vec::new();
+
+    // Sheet names and their relationship ids
+    let mut names = Vec::new();
+    let mut reader = open_xml(archive, "xl/workbook.xml")?;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"sheet" => {
+                let name = attribute(e, b"name")?.unwrap_or_default();
+                names.push((name, attribute(e, b"id")?.unwrap_or_default()));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    drop(reader);
+    // Part of each relationship id
+    let mut targets = HashMap::new();
+    let mut reader = open_xml(archive, "xl/_rels/workbook.xml.rels")?;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e)
+                if e.local_name().as_ref() == b"Relationship" =>
+            {
+                if let (Some(id), Some(target)) = (attribute(e, b"Id")?, attribute(e, b"Target")?) {
+                    let target = match target.strip_prefix('/') {
+                        Some(absolute) => absolute.to_string(),
+                        None => format!("xl/{target}"),
+                    };
+                    targets.insert(id, target);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    drop(reader);
+    // Shared strings, concatenating the runs of rich text
+    let mut strings = Vec::new();
+    if archive.index_for_name("xl/sharedStrings.xml").is_some() {
+        let mut reader = open_xml(archive, "xl/sharedStrings.xml")?;
+        let mut phonetic = false;
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(ref e) => match e.local_name().as_ref() {
+                    b"si" => strings.push(String::new()),
+                    b"rPh" => phonetic = true,
+                    _ => {}
+                },
+                Event::End(ref e) if e.local_name().as_ref() == b"rPh" => phonetic = false,
+                Event::Text(ref t) if !phonetic => {
+                    if let Some(s) = strings.last_mut() {
+                        s.push_str(&t.unescape()?);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        drop(reader);
+    }
+
+    // Whether the number format of each cell style is a date
+    let mut dates = Vec::new();
+    if archive.index_for_name("xl/styles.xml").is_some() {
+        let mut reader = open_xml(archive, "xl/styles.xml")?;
+        let mut custom = HashMap::new();
+        let mut xfs = false;
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(ref e) if e.local_name().as_ref() == b"cellXfs" => xfs = true,
+                Event::End(ref e) if e.local_name().as_ref() == b"cellXfs" => xfs = false,
+                Event::Start(ref e) | Event::Empty(ref e) => match e.local_name().as_ref() {
+                    b"numFmt" => {
+                        if let (Some(id), Some(code)) =
+                            (attribute(e, b"numFmtId")?, attribute(e, b"formatCode")?)
+                        {
+                            custom.insert(id, code);
+                        }
+                    }
+                    b"xf" if xfs => {
+                        let id = attribute(e, b"numFmtId")?.unwrap_or_default();
+                        dates.push(is_date_format(&id, &custom));
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        drop(reader);
+    }
+
+    let mut sheets = Vec::new();
+    for (name, id) in names {
+        let Some(target) = targets.get(&id) else {
+            continue;
+        };
+        let mut sheet = Sheet {
+            name,
+            rows: BTreeMap::new(),
+        };
+        let mut reader = open_xml(archive, target)?;
+        let (mut row, mut col): (RowNum, ColNum) = (0, 0);
+        let mut cells = BTreeMap::new();
+        let mut typ = None;
+        let mut date = false;
+        let mut text = String::new();
+        let mut value = false;
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(ref e) => match e.local_name().as_ref() {
+                    b"row" => {
+                        if let Some(r) = attribute(e, b"r")?.and_then(|r| r.parse().ok()) {
+                            row = RowNum::saturating_sub(r, 1);
+                        }
+                        col = 0;
+                    }
+                    b"c" => {
+                        if let Some((_, c)) = attribute(e, b"r")?.and_then(|r| cell_reference(&r)) {
+                            col = c;
+                        }
+                        typ = attribute(e, b"t")?;
+                        date = attribute(e, b"s")?
+                            .and_then(|s| s.parse::<usize>().ok())
+                            .and_then(|s| dates.get(s).copied())
+                            .unwrap_or(false);
+                        text.clear();
+                    }
+                    b"v" | b"t" => value = true,
+                    _ => {}
+                },
+                Event::Empty(ref e) if e.local_name().as_ref() == b"c" => {
+                    col = col.saturating_add(1);
+                }
+                Event::Text(ref t) if value => text.push_str(&t.unescape()?),
+                Event::End(ref e) => match e.local_name().as_ref() {
+                    b"v" | b"t" => value = false,
+                    b"c" => {
+                        let content = match typ.as_deref() {
+                            Some("s") => text
+                                .parse::<usize>()
+                                .ok()
+                                .and_then(|n| strings.get(n))
+                                .cloned()
+                                .unwrap_or_default(),
+                            Some("b") => match text.as_str() {
+                                "1" => "true".to_string(),
+                                _ => "false".to_string(),
+                            },
+                            Some("d") => {
+                                iso_date(&text).unwrap_or_else(|| std::mem::take(&mut text))
+                            }
+                            None | Some("n") if date => {
+                                serial_date(&text).unwrap_or_else(|| std::mem::take(&mut text))
+                            }
+                            _ => std::mem::take(&mut text),
+                        };
+                        if !content.is_empty() {
+                            cells.insert(col, content);
+                        }
+                        col = col.saturating_add(1);
+                    }
+                    b"row" => {
+                        if !cells.is_empty() {
+                            sheet.rows.insert(row, std::mem::take(&mut cells));
+                        }
+                        row = row.saturating_add(1);
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        sheets.push(sheet);
+    }
+    Ok(sheets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cell_reference, Location, Workbook};
+    use crate::sheet::{Config, Grid, Input, InputData, IntraData, OutputData};
+    use crate::Amount;
+    use chrono::{TimeZone, Utc};
+    use indoc::indoc;
+    use std::io::{Cursor, Write};
+
+    fn config() -> Config {
+        dungeon_ini::from_str(indoc! {"
+            [general]
+            assets = BTC
+            exchanges = Kraken
+            holders = Bob
+
+            [in_header]
+            timestamp = 0
+            asset = 1
+            exchange = 2
+            holder = 3
+            transaction_type = 4
+            spot_price = 5
+            crypto_in = 6
+            fiat_fee = 7
+            unique_id = 8
+
+            [out_header]
+            timestamp = 0
+            asset = 1
+            exchange = 2
+            holder = 3
+            transaction_type = 4
+            spot_price = 5
+            crypto_out_no_fee = 6
+            crypto_fee = 7
+
+            [intra_header]
+            timestamp = 0
+            asset = 1
+            from_exchange = 2
+            from_holder = 3
+            to_exchange = 4
+            to_holder = 5
+            crypto_sent = 6
+            crypto_received = 7
+        "})
+        .unwrap()
+    }
+
+    fn buy(unique_id: &str) -> InputData<'_> {
+        InputData {
+            timestamp: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: "40000.5".parse().unwrap(),
+            crypto_in: "0.12345678".parse().unwrap(),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn write<G: Grid>(grid: &mut G, config: &Config)
+    where
+        crate::sheet::ImportError: From<G::Error>,
+    {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(buy("b1")).unwrap();
+        writer.serialize(buy("b2")).unwrap();
+        let data = writer.into_inner().unwrap();
+        let row = InputData::write_table(grid, 0, &config.in_header.0, &data).unwrap();
+        let row = OutputData::write_table(grid, row + 2, &config.out_header.0, &[]).unwrap();
+        IntraData::write_table(grid, row + 2, &config.intra_header.0, &[]).unwrap();
+    }
+
+    /// An archive of hand written parts, like the ones saved by spreadsheet applications
+    fn archive(parts: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        let mut archive = zip.finish().unwrap();
+        archive.set_position(0);
+        archive
+    }
+
+    #[test]
+    fn should_parse_cell_reference() {
+        assert_eq!(Some((0, 0)), cell_reference("A1"));
+        assert_eq!(Some((4, 2)), cell_reference("C5"));
+        assert_eq!(Some((9, 27)), cell_reference("AB10"));
+        assert_eq!(None, cell_reference("A0"));
+        let location = Location {
+            sheet: "BTC".into(),
+            row: 9,
+            col: Some(27),
+        };
+        assert_eq!("BTC!AB10", location.to_string());
+    }
+
+    #[cfg(feature = "ods")]
+    #[test]
+    fn should_read_ods() {
+        let config = config();
+        let mut workbook = crate::ods::Workbook::new();
+        write(workbook.add_worksheet("BTC"), &config);
+        let mut ods = workbook.save_to_writer(Cursor::new(Vec::new())).unwrap();
+        ods.set_position(0);
+
+        let workbook = Workbook::from_reader(ods).unwrap();
+        let tables = workbook.sheet("BTC").unwrap().tables(&config).unwrap();
+        assert_eq!(vec![buy("b1"), buy("b2")], tables.input);
+        assert!(tables.output.is_empty());
+        assert!(tables.intra.is_empty());
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn should_read_xlsx() {
+        let config = config();
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet().set_name("BTC").unwrap();
+        write(worksheet, &config);
+        worksheet.write(3, 5, "forty thousand").unwrap();
+        let xlsx = workbook.save_to_buffer().unwrap();
+
+        let workbook = Workbook::from_reader(Cursor::new(xlsx)).unwrap();
+        let sheet = workbook.sheet("BTC").unwrap();
+        assert_eq!(Some("b1"), sheet.cell(2, 8));
+        let err = sheet.tables(&config).unwrap_err();
+        assert!(
+            matches!(err, super::Error::Cell(ref location, _) if location.to_string() == "BTC!F4")
+        );
+    }
+
+    #[test]
+    fn should_read_typed_ods_cells() {
+        let content = indoc! {r#"
+            <office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+             <office:body><office:spreadsheet><table:table table:name="BTC">
+              <table:table-row><table:table-cell office:value-type="string"><text:p>IN</text:p></table:table-cell></table:table-row>
+              <table:table-row><table:table-cell office:value-type="string"><text:p>timestamp</text:p></table:table-cell></table:table-row>
+              <table:table-row>
+               <table:table-cell office:value-type="date" office:date-value="2022-01-01T00:00:00"><text:p>01/01/22 00:00</text:p></table:table-cell>
+               <table:table-cell office:value-type="string"><text:p>BTC</text:p></table:table-cell>
+               <table:table-cell office:value-type="string"><text:p>Kraken</text:p></table:table-cell>
+               <table:table-cell office:value-type="string"><text:p>Bob</text:p></table:table-cell>
+               <table:table-cell office:value-type="string"><text:p>buy</text:p></table:table-cell>
+               <table:table-cell office:value-type="float" office:value="40000.5"><text:p>40,000.50</text:p></table:table-cell>
+               <table:table-cell office:value-type="float" office:value="1.2345678E-01"><text:p>0.12</text:p></table:table-cell>
+               <table:table-cell office:value-type="float" office:value="0"><text:p>0</text:p></table:table-cell>
+               <table:table-cell office:value-type="string"><text:p>b1</text:p></table:table-cell>
+              </table:table-row>
+              <table:table-row><table:table-cell office:value-type="string"><text:p>TABLE END</text:p></table:table-cell></table:table-row>
+             </table:table></office:spreadsheet></office:body>
+            </office:document-content>
+        "#};
+        let ods = archive(&[("content.xml", content)]);
+        let workbook = Workbook::from_reader(ods).unwrap();
+        let tables = workbook.sheet("BTC").unwrap().tables(&config()).unwrap();
+        assert_eq!(vec![buy("b1")], tables.input);
+    }
+
+    #[test]
+    fn should_read_typed_xlsx_cells() {
+        let workbook = indoc! {r#"
+            <workbook xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+             <sheets><sheet name="BTC" sheetId="1" r:id="rId1"/></sheets>
+            </workbook>
+        "#};
+        let rels = indoc! {r#"
+            <Relationships>
+             <Relationship Id="rId1" Target="worksheets/sheet1.xml"/>
+            </Relationships>
+        "#};
+        // Style 1 is a built in date, style 2 a custom date and style 3 a custom number
+        let styles = indoc! {r##"
+            <styleSheet>
+             <numFmts count="2">
+              <numFmt numFmtId="164" formatCode="yyyy\-mm\-dd hh:mm"/>
+              <numFmt numFmtId="165" formatCode="#,##0.00 [$EUR]"/>
+             </numFmts>
+             <cellXfs count="4">
+              <xf numFmtId="0"/><xf numFmtId="22"/><xf numFmtId="164"/><xf numFmtId="165"/>
+             </cellXfs>
+            </styleSheet>
+        "##};
+        let row = |r: u32, timestamp: &str, style: u32, unique_id: &str| {
+            format!(
+                r#"<row r="{r}"><c r="A{r}" s="{style}"><v>{timestamp}</v></c><c r="B{r}" t="inlineStr"><is><t>BTC</t></is></c><c r="C{r}" t="inlineStr"><is><t>Kraken</t></is></c><c r="D{r}" t="inlineStr"><is><t>Bob</t></is></c><c r="E{r}" t="inlineStr"><is><t>buy</t></is></c><c r="F{r}" s="3"><v>40000.5</v></c><c r="G{r}"><v>1.2345678E-1</v></c><c r="H{r}"><v>0</v></c><c r="I{r}" t="inlineStr"><is><t>{unique_id}</t></is></c></row>"#
+            )
+        };
+        let sheet = format!(
+            r#"<worksheet><sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>IN</t></is></c></row><row r="2"><c r="A2" t="inlineStr"><is><t>timestamp</t></is></c></row>{}{}<row r="5"><c r="A5" t="inlineStr"><is><t>TABLE END</t></is></c></row></sheetData></worksheet>"#,
+            row(3, "44562", 1, "b1"),
+            row(4, "44562.5", 2, "b2"),
+        );
+        let xlsx = archive(&[
+            ("xl/workbook.xml", workbook),
+            ("xl/_rels/workbook.xml.rels", rels),
+            ("xl/styles.xml", styles),
+            ("xl/worksheets/sheet1.xml", &sheet),
+        ]);
+        let workbook = Workbook::from_reader(xlsx).unwrap();
+        let tables = workbook.sheet("BTC").unwrap().tables(&config()).unwrap();
+        let noon = InputData {
+            timestamp: Utc.with_ymd_and_hms(2022, 1, 1, 12, 0, 0).unwrap(),
+            ..buy("b2")
+        };
+        assert_eq!(vec![buy("b1"), noon], tables.input);
+    }
+}
@@ -0,0 +1,284 @@
+/// report.rs
+///
+/// Classify realized gains as short or long term, and total them per tax year by asset,
+/// exchange and holder
+use crate::{
+    basis::{Gain, Matched},
+    Amount,
+};
+use chrono::{DateTime, Datelike, Months, Utc};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, io};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Term {
+    Short,
+    Long,
+}
+
+impl Term {
+    /// Long term when held for more than one year, IE: disposed of on or after the day after
+    /// the anniversary of the acquisition. The anniversary of February 29th is February 28th
+    pub fn new(acquired: DateTime<Utc>, disposed: DateTime<Utc>) -> Self {
+        let acquired = acquired.date_naive();
+        let anniversary = acquired
+            .checked_add_months(Months::new(12))
+            .expect("date in range");
+        match disposed.date_naive() > anniversary {
+            true => Term::Long,
+            false => Term::Short,
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Short => f.write_str("short"),
+            Self::Long => f.write_str("long"),
+        }
+    }
+}
+
+impl<'a> Matched<'a> {
+    pub fn term(&self, disposed: DateTime<Utc>) -> Term {
+        Term::new(self.acquired, disposed)
+    }
+}
+
+impl<'a> Gain<'a> {
+    /// Share of the proceeds of a matched lot, in proportion to its amount
+    pub fn proceeds_of(&self, matched: &Matched) -> Amount {
+        (self.proceeds * matched.amount)
+            .checked_div(self.amount)
+            .unwrap_or(Amount::ZERO)
+    }
+}
+
+/// Totals of one tax year, asset, exchange, holder and term
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportRow {
+    pub year: i32,
+    pub asset: String,
+    pub exchange: String,
+    pub holder: String,
+    pub term: Term,
+    pub amount: Amount,
+    pub proceeds: Amount,
+    pub cost_basis: Amount,
+    pub gain: Amount,
+}
+
+impl ReportRow {
+    pub const HEADERS: [&'static str; 9] = [
+        "year",
+        "asset",
+        "exchange",
+        "holder",
+        "term",
+        "amount",
+        "proceeds",
+        "cost_basis",
+        "gain",
+    ];
+}
+
+/// Capital gains per tax year, sorted by year, asset, exchange, holder and term
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub rows: Vec<ReportRow>,
+}
+
+impl Report {
+    /// Split each gain by the term of its matched lots
+    pub fn new(gains: &[Gain]) -> Self {
+        let mut totals = BTreeMap::new();
+        for gain in gains.iter() {
+            for matched in gain.lots.iter() {
+                let key = (
+                    gain.timestamp.year(),
+                    gain.asset,
+                    gain.exchange,
+                    gain.holder,
+                    matched.term(gain.timestamp),
+                );
+                let proceeds = gain.proceeds_of(matched);
+                let row = totals
+                    .entry(key)
+                    .or_insert((Amount::ZERO, Amount::ZERO, Amount::ZERO));
+                row.0 += matched.amount;
+                row.1 += proceeds;
+                row.2 += matched.cost_basis;
+            }
+        }
+        let rows = totals
+            .into_iter()
+            .map(
+                |((year, asset, exchange, holder, term), (amount, proceeds, cost_basis))| {
+                    ReportRow {
+                        year,
+                        asset: asset.to_string(),
+                        exchange: exchange.to_string(),
+                        holder: holder.to_string(),
+                        term,
+                        amount,
+                        proceeds,
+                        cost_basis,
+                        gain: proceeds - cost_basis,
+                    }
+                },
+            )
+            .collect();
+        Self { rows }
+    }
+
+    /// Rows of a single tax year
+    pub fn year(&self, year: i32) -> impl Iterator<Item = &ReportRow> {
+        self.rows.iter().filter(move |row| row.year == year)
+    }
+
+    pub fn write_csv<W>(&self, writer: W) -> Result<(), csv::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for row in self.rows.iter() {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the headers and rows starting at `row`, returning the last row written
+    #[cfg(feature = "xlsx")]
+    pub fn write_xlsx(
+        &self,
+        worksheet: &mut rust_xlsxwriter::Worksheet,
+        mut row: rust_xlsxwriter::RowNum,
+    ) -> Result<rust_xlsxwriter::RowNum, rust_xlsxwriter::XlsxError> {
+        for (col, header) in ReportRow::HEADERS.iter().enumerate() {
+            worksheet.write(row, col as rust_xlsxwriter::ColNum, *header)?;
+        }
+        for data in self.rows.iter() {
+            row += 1;
+            worksheet.write(row, 0, data.year)?;
+            worksheet.write(row, 1, &data.asset)?;
+            worksheet.write(row, 2, &data.exchange)?;
+            worksheet.write(row, 3, &data.holder)?;
+            worksheet.write(row, 4, data.term.to_string())?;
+            worksheet.write(row, 5, data.amount.to_f64())?;
+            worksheet.write(row, 6, data.proceeds.to_f64())?;
+            worksheet.write(row, 7, data.cost_basis.to_f64())?;
+            worksheet.write(row, 8, data.gain.to_f64())?;
+        }
+        Ok(row)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Report, Term};
+    use crate::basis::{Gain, Matched};
+    use crate::sheet::{AccountingMethod, Output};
+    use crate::Amount;
+    use chrono::{DateTime, TimeZone, Utc};
+    use indoc::indoc;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn should_classify_terms() {
+        let acquired = date(2021, 3, 15);
+        assert_eq!(Term::Short, Term::new(acquired, date(2021, 12, 31)));
+        assert_eq!(Term::Short, Term::new(acquired, date(2022, 3, 15)));
+        assert_eq!(Term::Long, Term::new(acquired, date(2022, 3, 16)));
+
+        // Leap years
+        let acquired = date(2020, 2, 29);
+        assert_eq!(Term::Short, Term::new(acquired, date(2021, 2, 28)));
+        assert_eq!(Term::Long, Term::new(acquired, date(2021, 3, 1)));
+        let acquired = date(2019, 3, 1);
+        assert_eq!(Term::Short, Term::new(acquired, date(2020, 2, 29)));
+        assert_eq!(Term::Short, Term::new(acquired, date(2020, 3, 1)));
+        assert_eq!(Term::Long, Term::new(acquired, date(2020, 3, 2)));
+    }
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn should_report_per_year() {
+        let lot = |lot, acquired, vol, cost_basis| Matched {
+            lot,
+            acquired,
+            amount: amount(vol),
+            cost_basis: amount(cost_basis),
+        };
+        let gain =
+            |unique_id, timestamp, holder, vol, proceeds, lots: Vec<Matched<'static>>| Gain {
+                unique_id,
+                timestamp,
+                asset: "BTC",
+                exchange: "Kraken",
+                holder,
+                typ: Output::Sell,
+                method: AccountingMethod::Fifo,
+                amount: amount(vol),
+                proceeds: amount(proceeds),
+                cost_basis: lots.iter().map(|m| m.cost_basis).sum(),
+                lots,
+            };
+        let gains = [
+            gain(
+                "s1",
+                date(2022, 6, 1),
+                "Bob",
+                "2",
+                "1000",
+                vec![
+                    lot("b1", date(2021, 1, 1), "1", "100"),
+                    lot("b2", date(2022, 1, 1), "1", "300"),
+                ],
+            ),
+            gain(
+                "s2",
+                date(2022, 7, 1),
+                "Bob",
+                "1",
+                "200",
+                vec![lot("b3", date(2022, 2, 1), "1", "400")],
+            ),
+            gain(
+                "s3",
+                date(2023, 1, 1),
+                "Alice",
+                "1",
+                "600",
+                vec![lot("b4", date(2021, 1, 1), "1", "100")],
+            ),
+        ];
+        let report = Report::new(&gains);
+        assert_eq!(3, report.rows.len());
+        let rows: Vec<_> = report.year(2022).collect();
+        assert_eq!(2, rows.len());
+        assert_eq!(
+            (Term::Short, amount("2"), amount("700")),
+            (rows[0].term, rows[0].amount, rows[0].proceeds)
+        );
+        assert_eq!((Term::Long, amount("400")), (rows[1].term, rows[1].gain));
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let expect = indoc! {"
+            year,asset,exchange,holder,term,amount,proceeds,cost_basis,gain
+            2022,BTC,Kraken,Bob,short,2,700,700,0
+            2022,BTC,Kraken,Bob,long,1,500,100,400
+            2023,BTC,Kraken,Alice,long,1,600,100,500
+        "};
+        assert_eq!(expect, String::from_utf8(csv).unwrap());
+    }
+}
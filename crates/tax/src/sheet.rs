@@ -2,16 +2,42 @@
 ///
 /// See more about the configuration here:
 /// https://github.com/eprbell/rp2/blob/main/docs/input_files.md#the-config-file
-use chrono::{DateTime, Utc};
+use crate::{validate::Table, Amount};
+use chrono::{DateTime, Datelike, Utc};
 use serde::{
     de::{self},
     Deserialize, Serialize,
 };
-use std::{collections::HashMap, fmt, io, str};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    path::PathBuf,
+    str,
+};
 use tracing::warn;
 
 macro_rules! impl_deserialize_header {
-    ($name:ty) => {
+    ($name:ty, $table:expr, required: [$($required:literal),*], optional: [$($optional:literal),*]) => {
+        impl $name {
+            /// Fields rp2 requires in the table
+            pub const REQUIRED: &'static [&'static str] = &[$($required),*];
+            /// Every field of the table
+            pub const FIELDS: &'static [&'static str] = &[$($required,)* $($optional),*];
+
+            /// Fields with their column, as configured
+            pub fn fields(&self) -> impl Iterator<Item = (&str, usize)> {
+                self.0
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, field)| !field.is_empty())
+                    .map(|(column, field)| (field.as_str(), column))
+            }
+
+            fn validate(&self, errors: &mut Vec<ConfigError>) {
+                validate_header($table, self.fields(), Self::REQUIRED, Self::FIELDS, errors)
+            }
+        }
+
         impl std::ops::Index<usize> for $name {
             type Output = String;
             fn index(&self, index: usize) -> &Self::Output {
@@ -30,50 +56,177 @@ macro_rules! impl_deserialize_header {
     };
 }
 
-macro_rules! impl_xlsx_writer {
-    ($ty:ident <$li:lifetime>) => {
-        #[cfg(feature = "xlsx")]
+/// Write rows of a table into a worksheet, placing each field in the column of its header
+macro_rules! impl_table_writer {
+    ($ty:ident <$li:lifetime>, $table:literal { $($($header:literal)|+ => $field:ident),* $(,)? }) => {
+        #[cfg(any(feature = "xlsx", feature = "ods"))]
         impl<$li> $ty<$li> {
-            pub fn write_headers(
-                worksheet: &mut rust_xlsxwriter::Worksheet,
-                row: rust_xlsxwriter::RowNum,
-                mut col: rust_xlsxwriter::ColNum,
-                headers: &Vec<String>,
-            ) -> Result<(), rust_xlsxwriter::XlsxError> {
+            pub fn write_headers<G: Grid>(
+                grid: &mut G,
+                row: RowNum,
+                mut col: ColNum,
+                headers: &[String],
+            ) -> Result<(), G::Error> {
                 for header in headers.iter() {
-                    worksheet.write(row, col, header)?;
+                    grid.write_string(row, col, header)?;
                     col += 1;
                 }
                 Ok(())
             }
 
-            pub fn write_data(
-                worksheet: &mut rust_xlsxwriter::Worksheet,
+            pub fn write_row<G: Grid>(
+                &self,
+                grid: &mut G,
+                row: RowNum,
+                col: ColNum,
+                headers: &[String],
+            ) -> Result<(), G::Error> {
+                for (n, header) in headers.iter().enumerate() {
+                    let col = col + n as ColNum;
+                    match header.as_str() {
+                        $($($header)|+ => self.$field.write_cell(grid, row, col)?,)*
+                        "" => {}
+                        field => warn!(field, "unknown header"),
+                    }
+                }
+                Ok(())
+            }
+
+            /// Write the csv rows after the header `row`, returning the last row written
+            pub fn write_data<G: Grid>(
+                grid: &mut G,
+                mut row: RowNum,
+                col: ColNum,
+                headers: &[String],
                 data: &[u8],
-            ) -> Result<(), ImportError> {
+            ) -> Result<RowNum, ImportError>
+            where
+                ImportError: From<G::Error>,
+            {
                 let mut reader = csv::Reader::from_reader(data);
                 let mut record = csv::StringRecord::new();
-                let headers = reader.headers()?.clone();
+                let csv_headers = reader.headers()?.clone();
                 while reader.read_record(&mut record)? {
-                    println!("{:?}", record);
-                    // let data = record.deserialize::<$ty>(Some(&headers))?;
-                    // worksheet.serialize(&data)?;
+                    let data = record.deserialize::<$ty>(Some(&csv_headers))?;
+                    row += 1;
+                    data.write_row(grid, row, col, headers)?;
                 }
-                Ok(())
+                Ok(row)
+            }
+
+            /// Write the table name, headers, csv rows and `TABLE END` marker starting at `row`,
+            /// returning the row of the marker
+            pub fn write_table<G: Grid>(
+                grid: &mut G,
+                row: RowNum,
+                headers: &[String],
+                data: &[u8],
+            ) -> Result<RowNum, ImportError>
+            where
+                ImportError: From<G::Error>,
+            {
+                grid.write_string(row, 0, $table)?;
+                Self::write_headers(grid, row + 1, 0, headers)?;
+                let row = Self::write_data(grid, row + 1, 0, headers, data)? + 1;
+                grid.write_string(row, 0, "TABLE END")?;
+                Ok(row)
             }
         }
     };
 }
 
+pub type RowNum = u32;
+pub type ColNum = u16;
+
+/// A worksheet the rp2 tables are written into
+pub trait Grid {
+    type Error;
+    fn write_string(&mut self, row: RowNum, col: ColNum, value: &str) -> Result<(), Self::Error>;
+    fn write_number(&mut self, row: RowNum, col: ColNum, value: Amount) -> Result<(), Self::Error>;
+}
+
 #[cfg(feature = "xlsx")]
-#[cfg_attr(feature = "xlsx", derive(thiserror::Error, Debug))]
+impl Grid for rust_xlsxwriter::Worksheet {
+    type Error = rust_xlsxwriter::XlsxError;
+    fn write_string(&mut self, row: RowNum, col: ColNum, value: &str) -> Result<(), Self::Error> {
+        rust_xlsxwriter::Worksheet::write_string(self, row, col, value).map(|_| ())
+    }
+
+    fn write_number(&mut self, row: RowNum, col: ColNum, value: Amount) -> Result<(), Self::Error> {
+        rust_xlsxwriter::Worksheet::write_number(self, row, col, value.to_f64()).map(|_| ())
+    }
+}
+
+/// A value written into a single worksheet cell
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+trait Cell {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error>;
+}
+
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl Cell for &str {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error> {
+        grid.write_string(row, col, self)
+    }
+}
+
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl Cell for Amount {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error> {
+        grid.write_number(row, col, *self)
+    }
+}
+
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl Cell for DateTime<Utc> {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error> {
+        grid.write_string(row, col, &crate::date::format(self).to_string())
+    }
+}
+
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl Cell for Input {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error> {
+        grid.write_string(row, col, &self.to_string())
+    }
+}
+
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl Cell for Output {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error> {
+        grid.write_string(row, col, &self.to_string())
+    }
+}
+
+/// Empty cell when missing
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl<T: Cell> Cell for Option<T> {
+    fn write_cell<G: Grid>(&self, grid: &mut G, row: RowNum, col: ColNum) -> Result<(), G::Error> {
+        match self {
+            Some(value) => value.write_cell(grid, row, col),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+#[derive(thiserror::Error, Debug)]
 pub enum ImportError {
     #[error("Read error {0}")]
     Read(#[from] csv::Error),
+    #[cfg(feature = "xlsx")]
     #[error("Write error {0}")]
     Write(#[from] rust_xlsxwriter::XlsxError),
 }
 
+/// Writing into an in memory sheet can't fail
+#[cfg(any(feature = "xlsx", feature = "ods"))]
+impl From<std::convert::Infallible> for ImportError {
+    fn from(infallible: std::convert::Infallible) -> Self {
+        match infallible {}
+    }
+}
+
 pub struct Visitor;
 impl<'de> de::Visitor<'de> for Visitor {
     type Value = Vec<String>;
@@ -92,6 +245,12 @@ impl<'de> de::Visitor<'de> for Visitor {
                     inner.push("".to_string());
                 }
             }
+            if !inner[value].is_empty() {
+                return Err(de::Error::custom(format_args!(
+                    "{} and {key} map to column {value}",
+                    inner[value]
+                )));
+            }
             inner[value] = key.to_owned();
         }
         Ok(inner)
@@ -113,17 +272,15 @@ pub struct General {
     pub holders: Vec<String>,
     pub spouse: Option<String>,
     pub generator: Option<String>,
-    #[serde(flatten)]
-    pub extra: HashMap<String, String>,
 }
 
 // https://github.com/eprbell/rp2/blob/main/docs/input_files.md#in-transaction-table-format
+/// Field of each column, empty when the column has none
 #[derive(Debug)]
 pub struct InputHeader(pub Vec<String>);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-#[cfg_attr(test, derive(PartialEq))]
 pub enum Input {
     Airdrop,
     Buy,
@@ -137,19 +294,19 @@ pub enum Input {
     Wages,
 }
 
-impl ToString for Input {
-    fn to_string(&self) -> String {
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Airdrop => "airdrop".to_string(),
-            Self::Buy => "buy".to_string(),
-            Self::Donate => "donate".to_string(),
-            Self::Gift => "gift".to_string(),
-            Self::Hardfork => "hardfork".to_string(),
-            Self::Income => "income".to_string(),
-            Self::Interest => "interest".to_string(),
-            Self::Mining => "mining".to_string(),
-            Self::Staking => "staking".to_string(),
-            Self::Wages => "wages".to_string(),
+            Self::Airdrop => f.write_str("airdrop"),
+            Self::Buy => f.write_str("buy"),
+            Self::Donate => f.write_str("donate"),
+            Self::Gift => f.write_str("gift"),
+            Self::Hardfork => f.write_str("hardfork"),
+            Self::Income => f.write_str("income"),
+            Self::Interest => f.write_str("interest"),
+            Self::Mining => f.write_str("mining"),
+            Self::Staking => f.write_str("staking"),
+            Self::Wages => f.write_str("wages"),
         }
     }
 }
@@ -162,22 +319,25 @@ pub struct InputData<'a> {
     pub asset: &'a str,
     pub exchange: &'a str,
     pub holder: &'a str,
-    #[serde(rename(serialize = "transaction_type", deserialize = "type"))]
+    #[serde(
+        rename(serialize = "transaction_type", deserialize = "type"),
+        alias = "transaction_type"
+    )]
     pub typ: Input,
-    pub spot_price: f32,
-    pub crypto_in: f32,
-    pub crypto_fee: Option<f32>,
-    pub fiat_in_no_fee: Option<f32>,
-    pub fiat_in_with_fee: Option<f32>,
-    pub fiat_fee: f32,
+    pub spot_price: Amount,
+    pub crypto_in: Amount,
+    pub crypto_fee: Option<Amount>,
+    pub fiat_in_no_fee: Option<Amount>,
+    pub fiat_in_with_fee: Option<Amount>,
+    pub fiat_fee: Amount,
     pub unique_id: &'a str,
     pub notes: Option<&'a str>,
 }
 
 impl<'a> InputData<'a> {
-    pub fn sort(headers: &Vec<String>, record: csv::StringRecord) -> csv::StringRecord {
+    pub fn sort(headers: &[String], record: csv::StringRecord) -> csv::StringRecord {
         let mut sorted = csv::StringRecord::new();
-        for (_idx, header) in headers.iter().enumerate() {
+        for header in headers.iter() {
             match header.as_str() {
                 "timestamp" => sorted.push_field(&record[0]),
                 "asset" => sorted.push_field(&record[1]),
@@ -203,16 +363,35 @@ impl<'a> InputData<'a> {
     }
 }
 
-impl_deserialize_header!(InputHeader);
-impl_xlsx_writer!(InputData<'a>);
+impl_deserialize_header!(InputHeader, Table::In, required: [
+    "timestamp", "asset", "exchange", "holder", "transaction_type", "spot_price", "crypto_in",
+    "fiat_fee"
+], optional: [
+    "crypto_fee", "fiat_in_no_fee", "fiat_in_with_fee", "unique_id", "notes"
+]);
+impl_table_writer!(InputData<'a>, "IN" {
+    "timestamp" => timestamp,
+    "asset" => asset,
+    "exchange" => exchange,
+    "holder" => holder,
+    "transaction_type" | "type" => typ,
+    "spot_price" => spot_price,
+    "crypto_in" => crypto_in,
+    "crypto_fee" => crypto_fee,
+    "fiat_in_no_fee" => fiat_in_no_fee,
+    "fiat_in_with_fee" => fiat_in_with_fee,
+    "fiat_fee" => fiat_fee,
+    "unique_id" => unique_id,
+    "notes" => notes,
+});
 
 // https://github.com/eprbell/rp2/blob/main/docs/input_files.md#out-transaction-table-format
+/// Field of each column, empty when the column has none
 #[derive(Debug)]
 pub struct OutputHeader(pub Vec<String>);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-#[cfg_attr(test, derive(PartialEq))]
 pub enum Output {
     Donate,
     Fee,
@@ -222,6 +401,19 @@ pub enum Output {
     Staking,
 }
 
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Donate => f.write_str("donate"),
+            Self::Fee => f.write_str("fee"),
+            Self::Gift => f.write_str("gift"),
+            Self::Lost => f.write_str("lost"),
+            Self::Sell => f.write_str("sell"),
+            Self::Staking => f.write_str("staking"),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct OutputData<'a> {
@@ -230,22 +422,45 @@ pub struct OutputData<'a> {
     pub asset: &'a str,
     pub exchange: &'a str,
     pub holder: &'a str,
-    #[serde(rename(serialize = "transaction_type", deserialize = "type"))]
+    #[serde(
+        rename(serialize = "transaction_type", deserialize = "type"),
+        alias = "transaction_type"
+    )]
     pub typ: Output,
-    pub spot_price: f32,
-    pub crypto_out_no_fee: f32,
-    pub crypto_fee: f32,
-    pub crypto_out_with_fee: Option<f32>,
-    pub fiat_out_no_fee: Option<f32>,
-    pub fiat_fee: Option<f32>,
+    pub spot_price: Amount,
+    pub crypto_out_no_fee: Amount,
+    pub crypto_fee: Amount,
+    pub crypto_out_with_fee: Option<Amount>,
+    pub fiat_out_no_fee: Option<Amount>,
+    pub fiat_fee: Option<Amount>,
     pub unique_id: &'a str,
     pub notes: Option<&'a str>,
 }
 
-impl_deserialize_header!(OutputHeader);
-impl_xlsx_writer!(OutputData<'a>);
+impl_deserialize_header!(OutputHeader, Table::Out, required: [
+    "timestamp", "asset", "exchange", "holder", "transaction_type", "spot_price",
+    "crypto_out_no_fee", "crypto_fee"
+], optional: [
+    "crypto_out_with_fee", "fiat_out_no_fee", "fiat_fee", "unique_id", "notes"
+]);
+impl_table_writer!(OutputData<'a>, "OUT" {
+    "timestamp" => timestamp,
+    "asset" => asset,
+    "exchange" => exchange,
+    "holder" => holder,
+    "transaction_type" | "type" => typ,
+    "spot_price" => spot_price,
+    "crypto_out_no_fee" => crypto_out_no_fee,
+    "crypto_fee" => crypto_fee,
+    "crypto_out_with_fee" => crypto_out_with_fee,
+    "fiat_out_no_fee" => fiat_out_no_fee,
+    "fiat_fee" => fiat_fee,
+    "unique_id" => unique_id,
+    "notes" => notes,
+});
 
 // https://github.com/eprbell/rp2/blob/main/docs/input_files.md#intra-transaction-table-format
+/// Field of each column, empty when the column has none
 #[derive(Debug)]
 pub struct IntraHeader(pub Vec<String>);
 
@@ -259,31 +474,74 @@ pub struct IntraData<'a> {
     pub from_holder: &'a str,
     pub to_exchange: &'a str,
     pub to_holder: &'a str,
-    pub spot_price: Option<f32>,
-    pub crypto_sent: f32,
-    pub crypto_received: f32,
+    pub spot_price: Option<Amount>,
+    pub crypto_sent: Amount,
+    pub crypto_received: Amount,
     pub unique_id: &'a str,
     pub notes: Option<&'a str>,
 }
 
-impl_deserialize_header!(IntraHeader);
-impl_xlsx_writer!(IntraData<'a>);
+impl_deserialize_header!(IntraHeader, Table::Intra, required: [
+    "timestamp", "asset", "from_exchange", "from_holder", "to_exchange", "to_holder",
+    "spot_price", "crypto_sent", "crypto_received"
+], optional: [
+    "unique_id", "notes"
+]);
+impl_table_writer!(IntraData<'a>, "INTRA" {
+    "timestamp" => timestamp,
+    "asset" => asset,
+    "from_exchange" => from_exchange,
+    "from_holder" => from_holder,
+    "to_exchange" => to_exchange,
+    "to_holder" => to_holder,
+    "spot_price" => spot_price,
+    "crypto_sent" => crypto_sent,
+    "crypto_received" => crypto_received,
+    "unique_id" => unique_id,
+    "notes" => notes,
+});
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum AccountingMethod {
+    #[default]
     Fifo,
     Lifo,
     Hifo,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "snake_case")]
+impl fmt::Display for AccountingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fifo => f.write_str("fifo"),
+            Self::Lifo => f.write_str("lifo"),
+            Self::Hifo => f.write_str("hifo"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(transparent)]
 pub struct AccountingMethods {
-    #[serde(flatten)]
     pub year: HashMap<u16, AccountingMethod>,
 }
 
+impl AccountingMethods {
+    /// The method of a tax year, FIFO when the year is not configured
+    pub fn get(&self, year: u16) -> AccountingMethod {
+        self.year.get(&year).copied().unwrap_or_default()
+    }
+}
+
+/// An exchange export to import, listed as repeated `[account]` sections
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Account {
+    pub exchange: String,
+    pub holder: String,
+    pub file: PathBuf,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Config {
@@ -292,6 +550,150 @@ pub struct Config {
     pub out_header: OutputHeader,
     pub intra_header: IntraHeader,
     pub accounting_methods: Option<AccountingMethods>,
+    #[serde(default, rename = "account")]
+    pub accounts: Vec<Account>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("{0} header is missing {1}")]
+    MissingField(Table, &'static str),
+    #[error("{0} header has unknown field {1}")]
+    UnknownField(Table, String),
+    #[error("{0} header maps {1} more than once")]
+    DuplicateField(Table, String),
+    #[error("general.{0} is empty")]
+    Empty(&'static str),
+    #[error("accounting method of {0} is in the future")]
+    FutureYear(u16),
+    #[error("unknown asset {0}")]
+    UnknownAsset(String),
+    #[error("unknown exchange {0}")]
+    UnknownExchange(String),
+    #[error("unknown holder {0}")]
+    UnknownHolder(String),
+}
+
+/// A list of names in `[general]`
+#[derive(Debug, Clone, Copy)]
+enum List {
+    Assets,
+    Exchanges,
+    Holders,
+}
+
+/// `type` is accepted as the name of the `transaction_type` column
+fn validate_header<'a>(
+    table: Table,
+    fields: impl Iterator<Item = (&'a str, usize)>,
+    required: &[&'static str],
+    known: &[&str],
+    errors: &mut Vec<ConfigError>,
+) {
+    let mut names = HashSet::new();
+    for (field, _) in fields {
+        let name = match field {
+            "type" => "transaction_type",
+            name => name,
+        };
+        if !known.contains(&name) {
+            errors.push(ConfigError::UnknownField(table, field.into()));
+        }
+        if !names.insert(name) {
+            errors.push(ConfigError::DuplicateField(table, field.into()));
+        }
+    }
+    for field in required.iter() {
+        if !names.contains(field) {
+            errors.push(ConfigError::MissingField(table, field));
+        }
+    }
+}
+
+impl Config {
+    /// Check the headers of each table, the general lists, the accounting method years and the
+    /// exchanges and holders of the accounts
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        self.validate_in(Utc::now().year())
+    }
+
+    fn validate_in(&self, year: i32) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        self.in_header.validate(&mut errors);
+        self.out_header.validate(&mut errors);
+        self.intra_header.validate(&mut errors);
+        for (name, list) in [
+            ("assets", &self.general.assets),
+            ("exchanges", &self.general.exchanges),
+            ("holders", &self.general.holders),
+        ] {
+            if list.is_empty() {
+                errors.push(ConfigError::Empty(name));
+            }
+        }
+        if let Some(methods) = self.accounting_methods.as_ref() {
+            let mut years: Vec<_> = methods.year.keys().filter(|y| **y as i32 > year).collect();
+            years.sort();
+            errors.extend(years.into_iter().map(|y| ConfigError::FutureYear(*y)));
+        }
+        for account in self.accounts.iter() {
+            self.check(&mut errors, List::Exchanges, &account.exchange);
+            self.check(&mut errors, List::Holders, &account.holder);
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Check the assets, exchanges and holders of the rows to import are listed in `[general]`,
+    /// reporting each unknown name once
+    pub fn validate_data(
+        &self,
+        inputs: &[InputData<'_>],
+        outputs: &[OutputData<'_>],
+        intras: &[IntraData<'_>],
+    ) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        for data in inputs.iter() {
+            self.check(&mut errors, List::Assets, data.asset);
+            self.check(&mut errors, List::Exchanges, data.exchange);
+            self.check(&mut errors, List::Holders, data.holder);
+        }
+        for data in outputs.iter() {
+            self.check(&mut errors, List::Assets, data.asset);
+            self.check(&mut errors, List::Exchanges, data.exchange);
+            self.check(&mut errors, List::Holders, data.holder);
+        }
+        for data in intras.iter() {
+            self.check(&mut errors, List::Assets, data.asset);
+            self.check(&mut errors, List::Exchanges, data.from_exchange);
+            self.check(&mut errors, List::Exchanges, data.to_exchange);
+            self.check(&mut errors, List::Holders, data.from_holder);
+            self.check(&mut errors, List::Holders, data.to_holder);
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    fn check(&self, errors: &mut Vec<ConfigError>, list: List, name: &str) {
+        let (known, error) = match list {
+            List::Assets => (&self.general.assets, ConfigError::UnknownAsset(name.into())),
+            List::Exchanges => (
+                &self.general.exchanges,
+                ConfigError::UnknownExchange(name.into()),
+            ),
+            List::Holders => (
+                &self.general.holders,
+                ConfigError::UnknownHolder(name.into()),
+            ),
+        };
+        if !known.iter().any(|s| s == name) && !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
 }
 
 pub struct AssetTables<W>
@@ -307,6 +709,7 @@ impl<W> AssetTables<W>
 where
     W: io::Write,
 {
+    #[allow(clippy::result_large_err)]
     pub fn into_inner(self) -> Result<(W, W, W), csv::IntoInnerError<csv::Writer<W>>> {
         Ok((
             self.input.into_inner()?,
@@ -318,9 +721,87 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::{AccountingMethod, Config};
+    use super::{AccountingMethod, Config, ConfigError, Input, InputData};
+    use crate::validate::Table;
+    use crate::Amount;
+    use chrono::{TimeZone, Utc};
     use indoc::indoc;
 
+    fn buy(unique_id: &str) -> InputData<'_> {
+        InputData {
+            timestamp: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: "40000.5".parse().unwrap(),
+            crypto_in: "0.25".parse().unwrap(),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: Some("order"),
+        }
+    }
+
+    #[test]
+    fn should_read_written_rows() {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(buy("b1")).unwrap();
+        let data = writer.into_inner().unwrap();
+        let mut reader = csv::Reader::from_reader(data.as_slice());
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(Some("transaction_type"), headers.get(4));
+        let record = reader.records().next().unwrap().unwrap();
+        let row = record.deserialize::<InputData>(Some(&headers)).unwrap();
+        assert_eq!(buy("b1"), row);
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn should_write_tables() {
+        use super::{IntraData, OutputData};
+        let headers: Vec<String> = ["timestamp", "", "asset", "crypto_in", "notes"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(buy("b1")).unwrap();
+        writer.serialize(buy("b2")).unwrap();
+        let data = writer.into_inner().unwrap();
+
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet().set_name("BTC").unwrap();
+        let row = InputData::write_table(worksheet, 0, &headers, &data).unwrap();
+        assert_eq!(4, row);
+        let row = OutputData::write_table(worksheet, row + 2, &headers, &[]).unwrap();
+        assert_eq!(8, row);
+        let row = IntraData::write_table(worksheet, row + 2, &headers, &[]).unwrap();
+        assert_eq!(12, row);
+
+        let xlsx = workbook.save_to_buffer().unwrap();
+        let workbook = crate::reader::Workbook::from_reader(std::io::Cursor::new(xlsx)).unwrap();
+        let sheet = workbook.sheet("BTC").unwrap();
+        assert_eq!(Some("IN"), sheet.cell(0, 0));
+        assert_eq!(Some("timestamp"), sheet.cell(1, 0));
+        assert_eq!(
+            Some("2022-01-01 00:00:00.000000000+00:00"),
+            sheet.cell(2, 0)
+        );
+        assert_eq!(None, sheet.cell(1, 1));
+        assert_eq!(Some("crypto_in"), sheet.cell(1, 3));
+        assert_eq!(Some("BTC"), sheet.cell(2, 2));
+        assert_eq!(None, sheet.cell(2, 1));
+        assert_eq!(Some("0.25"), sheet.cell(2, 3));
+        assert_eq!(Some("order"), sheet.cell(3, 4));
+        assert_eq!(Some("TABLE END"), sheet.cell(4, 0));
+        assert_eq!(Some("OUT"), sheet.cell(6, 0));
+        assert_eq!(Some("TABLE END"), sheet.cell(8, 0));
+        assert_eq!(Some("INTRA"), sheet.cell(10, 0));
+        assert_eq!(Some("TABLE END"), sheet.cell(12, 0));
+    }
+
     #[test]
     fn should_parse_config() {
         let input = indoc! {r#"
@@ -371,21 +852,32 @@ mod test {
             2022 = hifo
             2023 = fifo
 
+            [account]
+            exchange = kraken
+            holder = Bob
+            file = exports/kraken-2023.csv
+
+            [account]
+            exchange = coinbase
+            holder = Alice
+            file = exports/coinbase-2023.csv
+
         "#};
-        let config = dungeon_ini::from_str::<Config>(input).unwrap();
+        let (config, unknown) = dungeon_ini::de::Options::default()
+            .from_str_with_unknown::<Config>(input)
+            .unwrap();
         assert_eq!(4, config.general.assets.len());
         assert_eq!("B1", config.general.assets[0]);
         assert_eq!("B2", config.general.assets[1]);
         assert_eq!("B3", config.general.assets[2]);
         assert_eq!("B4", config.general.assets[3]);
         assert_eq!(
-            Some("debug"),
-            config
-                .general
-                .extra
-                .get("meta")
-                .as_ref()
-                .map(|s| s.as_str())
+            vec![dungeon_ini::de::Unknown::Key {
+                section: Some("general".to_string()),
+                key: "meta".to_string(),
+                line: Some(5),
+            }],
+            unknown
         );
         assert_eq!("timestamp", config.in_header.0[0]);
         assert_eq!("asset", config.in_header[6]);
@@ -421,5 +913,133 @@ mod test {
             Some(&AccountingMethod::Hifo),
             config.accounting_methods.unwrap().year.get(&2022)
         );
+        assert_eq!(2, config.accounts.len());
+        assert_eq!("kraken", config.accounts[0].exchange);
+        assert_eq!("Bob", config.accounts[0].holder);
+        assert_eq!(
+            std::path::Path::new("exports/kraken-2023.csv"),
+            config.accounts[0].file
+        );
+        assert_eq!("coinbase", config.accounts[1].exchange);
+        assert_eq!("Alice", config.accounts[1].holder);
+
+        // Same config with command line overrides applied
+        let mut document = dungeon_ini::parse_document(input).unwrap();
+        dungeon_ini::overrides::apply(&mut document, ["general.holders += Carol"]).unwrap();
+        let config = dungeon_ini::from_document::<Config>(&document).unwrap();
+        assert_eq!(vec!["Bob", "Alice", "Carol"], config.general.holders);
+        assert_eq!(expect_input, config.in_header.0.as_slice());
+        assert_eq!(2, config.accounts.len());
+        assert_eq!(
+            Some(&AccountingMethod::Fifo),
+            config.accounting_methods.unwrap().year.get(&2023)
+        );
+    }
+
+    #[test]
+    fn should_parse_config_with_typed_scalars() {
+        let input = indoc! {r#"
+            [general]
+            assets = BTC
+            exchanges = Kraken
+            holders = Bob
+            meta = true
+            scale = 1.5
+
+            [in_header]
+            timestamp = 0
+
+            [out_header]
+            timestamp = 0
+
+            [intra_header]
+            timestamp = 0
+        "#};
+        let (config, unknown) = dungeon_ini::de::Options::default()
+            .from_str_with_unknown::<Config>(input)
+            .unwrap();
+        assert_eq!(vec!["Bob"], config.general.holders);
+        let keys: Vec<_> = unknown.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            vec!["general.meta at line 5", "general.scale at line 6"],
+            keys
+        );
+    }
+
+    #[test]
+    fn should_validate_config() {
+        let input = indoc! {r#"
+            [general]
+            assets = BTC
+            exchanges = Kraken
+            holders = Bob
+
+            [in_header]
+            timestamp = 0
+            asset = 1
+            exchange = 2
+            holder = 3
+            type = 4
+            transaction_type = 5
+            spot_price = 8
+            crypto_in = 6
+            fee = 7
+
+            [out_header]
+            timestamp = 0
+            asset = 1
+            exchange = 2
+            holder = 3
+            transaction_type = 4
+            spot_price = 5
+            crypto_out_no_fee = 6
+            crypto_fee = 7
+
+            [intra_header]
+            timestamp = 0
+            asset = 1
+            from_exchange = 2
+            from_holder = 3
+            to_exchange = 4
+            to_holder = 5
+            spot_price = 6
+            crypto_sent = 7
+            crypto_received = 8
+
+            [accounting_methods]
+            2022 = fifo
+            2031 = lifo
+
+            [account]
+            exchange = kraken
+            holder = Alice
+            file = kraken.csv
+        "#};
+        let config = dungeon_ini::from_str::<Config>(input).unwrap();
+        assert_eq!(
+            Err(vec![
+                ConfigError::DuplicateField(Table::In, "transaction_type".into()),
+                ConfigError::UnknownField(Table::In, "fee".into()),
+                ConfigError::MissingField(Table::In, "fiat_fee"),
+                ConfigError::FutureYear(2031),
+                ConfigError::UnknownExchange("kraken".into()),
+                ConfigError::UnknownHolder("Alice".into()),
+            ]),
+            config.validate_in(2024)
+        );
+        assert_eq!(Ok(()), config.validate_data(&[buy("b1")], &[], &[]));
+        let mut row = buy("b2");
+        row.holder = "Carol";
+        assert_eq!(
+            Err(vec![ConfigError::UnknownHolder("Carol".into())]),
+            config.validate_data(&[row, buy("b1"), buy("b3")], &[], &[])
+        );
+
+        let input = input.replace("spot_price = 8", "spot_price = 5");
+        let err = dungeon_ini::from_str::<Config>(&input).unwrap_err();
+        assert_eq!(
+            "in_header at line 6: transaction_type and spot_price map to column 5",
+            err.to_string()
+        );
     }
 }
@@ -0,0 +1,328 @@
+/// snapshot.rs
+///
+/// The open lots at a point in time, with their cost basis and unrealized gain at a price. Lots
+/// are kept per wallet and moved by transfers, so an open lot is reported at the exchange and
+/// holder which holds it
+use crate::{
+    basis::{self, Error, Ledger, Wallet},
+    sheet::{AccountingMethods, InputData, IntraData, OutputData},
+    Amount,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io;
+
+/// The part of a lot which was not disposed of
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub asset: String,
+    pub exchange: String,
+    pub holder: String,
+    pub lot: String,
+    pub acquired: DateTime<Utc>,
+    pub amount: Amount,
+    pub cost_basis: Amount,
+    /// The price of one unit of the asset, when known
+    pub price: Option<Amount>,
+}
+
+impl Position {
+    pub const HEADERS: [&'static str; 10] = [
+        "asset",
+        "exchange",
+        "holder",
+        "lot",
+        "acquired",
+        "amount",
+        "cost_basis",
+        "price",
+        "value",
+        "unrealized",
+    ];
+
+    pub fn value(&self) -> Option<Amount> {
+        self.price.map(|price| self.amount * price)
+    }
+
+    /// Gain (or loss when negative) if the position was sold at its price
+    pub fn unrealized(&self) -> Option<Amount> {
+        self.value().map(|value| value - self.cost_basis)
+    }
+}
+
+/// A position with its value and unrealized gain, as exported
+#[derive(Serialize)]
+struct Row<'a> {
+    asset: &'a str,
+    exchange: &'a str,
+    holder: &'a str,
+    lot: &'a str,
+    #[serde(with = "crate::date")]
+    acquired: DateTime<Utc>,
+    amount: Amount,
+    cost_basis: Amount,
+    price: Option<Amount>,
+    value: Option<Amount>,
+    unrealized: Option<Amount>,
+}
+
+impl<'a> From<&'a Position> for Row<'a> {
+    fn from(position: &'a Position) -> Self {
+        Self {
+            asset: &position.asset,
+            exchange: &position.exchange,
+            holder: &position.holder,
+            lot: &position.lot,
+            acquired: position.acquired,
+            amount: position.amount,
+            cost_basis: position.cost_basis,
+            price: position.price,
+            value: position.value(),
+            unrealized: position.unrealized(),
+        }
+    }
+}
+
+/// Open positions sorted by holder, asset, exchange and acquisition
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    pub positions: Vec<Position>,
+}
+
+impl Snapshot {
+    /// Replay the transactions up to and including `timestamp`, with the accounting methods
+    /// of the tax years applied within each wallet
+    pub fn new(
+        methods: Option<&AccountingMethods>,
+        inputs: &[InputData<'_>],
+        outputs: &[OutputData<'_>],
+        intras: &[IntraData<'_>],
+        timestamp: DateTime<Utc>,
+    ) -> Result<Self, Error> {
+        let mut ledger: Ledger<Wallet> = Ledger::new(methods);
+        basis::replay(&mut ledger, inputs, outputs, intras, timestamp)?;
+        let mut positions: Vec<_> = ledger
+            .keys()
+            .flat_map(|wallet| ledger.lots(wallet))
+            .map(|lot| Position {
+                asset: lot.asset.to_string(),
+                exchange: lot.exchange.to_string(),
+                holder: lot.holder.to_string(),
+                lot: lot.unique_id.to_string(),
+                acquired: lot.timestamp,
+                amount: lot.remaining,
+                cost_basis: lot.remaining_cost,
+                price: None,
+            })
+            .collect();
+        positions.sort_by(|a, b| {
+            (&a.holder, &a.asset, &a.exchange, a.acquired, &a.lot).cmp(&(
+                &b.holder,
+                &b.asset,
+                &b.exchange,
+                b.acquired,
+                &b.lot,
+            ))
+        });
+        Ok(Self {
+            timestamp,
+            positions,
+        })
+    }
+
+    /// Price the positions of an asset
+    pub fn price(&mut self, asset: &str, price: Amount) {
+        self.positions
+            .iter_mut()
+            .filter(|position| position.asset == asset)
+            .for_each(|position| position.price = Some(price));
+    }
+
+    /// Positions of a holder
+    pub fn holder<'s>(&'s self, holder: &'s str) -> impl Iterator<Item = &'s Position> {
+        self.positions.iter().filter(move |p| p.holder == holder)
+    }
+
+    pub fn cost_basis(&self) -> Amount {
+        self.positions.iter().map(|p| p.cost_basis).sum()
+    }
+
+    /// Unrealized gain of the priced positions
+    pub fn unrealized(&self) -> Amount {
+        self.positions.iter().filter_map(Position::unrealized).sum()
+    }
+
+    pub fn write_csv<W>(&self, writer: W) -> Result<(), csv::Error>
+    where
+        W: io::Write,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        for position in self.positions.iter() {
+            writer.serialize(Row::from(position))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "xlsx")]
+    pub fn write_xlsx(
+        &self,
+        worksheet: &mut rust_xlsxwriter::Worksheet,
+        mut row: rust_xlsxwriter::RowNum,
+    ) -> Result<rust_xlsxwriter::RowNum, rust_xlsxwriter::XlsxError> {
+        use rust_xlsxwriter::{ColNum, Format};
+        let bold = Format::new().set_bold();
+        let dollars = Format::new().set_num_format("#,##0.00");
+        for (col, header) in Position::HEADERS.iter().enumerate() {
+            worksheet.write_with_format(row, col as ColNum, *header, &bold)?;
+        }
+        for position in self.positions.iter() {
+            row += 1;
+            worksheet.write(row, 0, &position.asset)?;
+            worksheet.write(row, 1, &position.exchange)?;
+            worksheet.write(row, 2, &position.holder)?;
+            worksheet.write(row, 3, &position.lot)?;
+            worksheet.write(row, 4, crate::date::format(&position.acquired).to_string())?;
+            worksheet.write(row, 5, position.amount.to_f64())?;
+            worksheet.write_with_format(row, 6, position.cost_basis.to_f64(), &dollars)?;
+            if let Some(price) = position.price {
+                worksheet.write_with_format(row, 7, price.to_f64(), &dollars)?;
+            }
+            if let Some(value) = position.value() {
+                worksheet.write_with_format(row, 8, value.to_f64(), &dollars)?;
+            }
+            if let Some(unrealized) = position.unrealized() {
+                worksheet.write_with_format(row, 9, unrealized.to_f64(), &dollars)?;
+            }
+        }
+        Ok(row)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Snapshot;
+    use crate::{
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        Amount,
+    };
+    use chrono::{DateTime, TimeZone, Utc};
+    use indoc::indoc;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+    }
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn buy<'a>(
+        unique_id: &'a str,
+        timestamp: DateTime<Utc>,
+        vol: &str,
+        price: &str,
+    ) -> InputData<'a> {
+        InputData {
+            timestamp,
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: amount(price),
+            crypto_in: amount(vol),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn should_snapshot_open_lots() {
+        let inputs = [
+            buy("b1", date(2022, 1, 1), "1", "100"),
+            buy("b2", date(2022, 2, 1), "2", "200"),
+            buy("b3", date(2023, 1, 1), "1", "300"),
+        ];
+        let outputs = [OutputData {
+            timestamp: date(2022, 3, 1),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: amount("250"),
+            crypto_out_no_fee: amount("1.5"),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: None,
+            fiat_fee: None,
+            unique_id: "s1",
+            notes: None,
+        }];
+        let mut snapshot = Snapshot::new(None, &inputs, &outputs, &[], date(2022, 12, 31)).unwrap();
+        assert_eq!(1, snapshot.positions.len());
+        assert_eq!("b2", snapshot.positions[0].lot);
+        assert_eq!(amount("1.5"), snapshot.positions[0].amount);
+        assert_eq!(amount("300"), snapshot.cost_basis());
+
+        snapshot.price("BTC", amount("150"));
+        assert_eq!(amount("-75"), snapshot.unrealized());
+        let mut csv = Vec::new();
+        snapshot.write_csv(&mut csv).unwrap();
+        let expect = indoc! {"
+            asset,exchange,holder,lot,acquired,amount,cost_basis,price,value,unrealized
+            BTC,Kraken,Bob,b2,2022-02-01 00:00:00.000000000+00:00,1.5,300,150,225,-75
+        "};
+        assert_eq!(expect, String::from_utf8(csv).unwrap());
+    }
+
+    #[test]
+    fn should_move_lots_on_transfer() {
+        let inputs = [
+            buy("b1", date(2022, 1, 1), "1", "100"),
+            buy("b2", date(2022, 2, 1), "1", "200"),
+        ];
+        let intras = [IntraData {
+            timestamp: date(2022, 3, 1),
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Ledger",
+            to_holder: "Alice",
+            spot_price: Some(amount("300")),
+            crypto_sent: amount("1.5"),
+            crypto_received: amount("1.5"),
+            unique_id: "t1",
+            notes: None,
+        }];
+        let snapshot = Snapshot::new(None, &inputs, &[], &intras, date(2022, 12, 31)).unwrap();
+        let positions: Vec<_> = snapshot
+            .positions
+            .iter()
+            .map(|p| {
+                (
+                    p.holder.as_str(),
+                    p.exchange.as_str(),
+                    p.lot.as_str(),
+                    p.amount,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("Alice", "Ledger", "b1", amount("1")),
+                ("Alice", "Ledger", "b2", amount("0.5")),
+                ("Bob", "Kraken", "b2", amount("0.5")),
+            ],
+            positions
+        );
+        assert_eq!(
+            amount("200"),
+            snapshot.holder("Alice").map(|p| p.cost_basis).sum()
+        );
+    }
+}
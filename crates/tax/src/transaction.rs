@@ -0,0 +1,450 @@
+/// transaction.rs
+///
+/// Owned rp2 transactions, which outlive the config and the csv record they were read from
+use crate::{
+    sheet::{Input, InputData, IntraData, Output, OutputData},
+    Amount,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
+
+/// Owned [`InputData`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InTransaction {
+    #[serde(with = "crate::date")]
+    pub timestamp: DateTime<Utc>,
+    pub asset: String,
+    pub exchange: String,
+    pub holder: String,
+    #[serde(
+        rename(serialize = "transaction_type", deserialize = "type"),
+        alias = "transaction_type"
+    )]
+    pub typ: Input,
+    pub spot_price: Amount,
+    pub crypto_in: Amount,
+    pub crypto_fee: Option<Amount>,
+    pub fiat_in_no_fee: Option<Amount>,
+    pub fiat_in_with_fee: Option<Amount>,
+    pub fiat_fee: Amount,
+    pub unique_id: String,
+    pub notes: Option<String>,
+}
+
+impl<'a> From<&InputData<'a>> for InTransaction {
+    fn from(data: &InputData<'a>) -> Self {
+        Self {
+            timestamp: data.timestamp,
+            asset: data.asset.to_string(),
+            exchange: data.exchange.to_string(),
+            holder: data.holder.to_string(),
+            typ: data.typ,
+            spot_price: data.spot_price,
+            crypto_in: data.crypto_in,
+            crypto_fee: data.crypto_fee,
+            fiat_in_no_fee: data.fiat_in_no_fee,
+            fiat_in_with_fee: data.fiat_in_with_fee,
+            fiat_fee: data.fiat_fee,
+            unique_id: data.unique_id.to_string(),
+            notes: data.notes.map(str::to_string),
+        }
+    }
+}
+
+impl<'a> From<&'a InTransaction> for InputData<'a> {
+    fn from(tx: &'a InTransaction) -> Self {
+        Self {
+            timestamp: tx.timestamp,
+            asset: &tx.asset,
+            exchange: &tx.exchange,
+            holder: &tx.holder,
+            typ: tx.typ,
+            spot_price: tx.spot_price,
+            crypto_in: tx.crypto_in,
+            crypto_fee: tx.crypto_fee,
+            fiat_in_no_fee: tx.fiat_in_no_fee,
+            fiat_in_with_fee: tx.fiat_in_with_fee,
+            fiat_fee: tx.fiat_fee,
+            unique_id: &tx.unique_id,
+            notes: tx.notes.as_deref(),
+        }
+    }
+}
+
+/// Owned [`OutputData`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutTransaction {
+    #[serde(with = "crate::date")]
+    pub timestamp: DateTime<Utc>,
+    pub asset: String,
+    pub exchange: String,
+    pub holder: String,
+    #[serde(
+        rename(serialize = "transaction_type", deserialize = "type"),
+        alias = "transaction_type"
+    )]
+    pub typ: Output,
+    pub spot_price: Amount,
+    pub crypto_out_no_fee: Amount,
+    pub crypto_fee: Amount,
+    pub crypto_out_with_fee: Option<Amount>,
+    pub fiat_out_no_fee: Option<Amount>,
+    pub fiat_fee: Option<Amount>,
+    pub unique_id: String,
+    pub notes: Option<String>,
+}
+
+impl<'a> From<&OutputData<'a>> for OutTransaction {
+    fn from(data: &OutputData<'a>) -> Self {
+        Self {
+            timestamp: data.timestamp,
+            asset: data.asset.to_string(),
+            exchange: data.exchange.to_string(),
+            holder: data.holder.to_string(),
+            typ: data.typ,
+            spot_price: data.spot_price,
+            crypto_out_no_fee: data.crypto_out_no_fee,
+            crypto_fee: data.crypto_fee,
+            crypto_out_with_fee: data.crypto_out_with_fee,
+            fiat_out_no_fee: data.fiat_out_no_fee,
+            fiat_fee: data.fiat_fee,
+            unique_id: data.unique_id.to_string(),
+            notes: data.notes.map(str::to_string),
+        }
+    }
+}
+
+impl<'a> From<&'a OutTransaction> for OutputData<'a> {
+    fn from(tx: &'a OutTransaction) -> Self {
+        Self {
+            timestamp: tx.timestamp,
+            asset: &tx.asset,
+            exchange: &tx.exchange,
+            holder: &tx.holder,
+            typ: tx.typ,
+            spot_price: tx.spot_price,
+            crypto_out_no_fee: tx.crypto_out_no_fee,
+            crypto_fee: tx.crypto_fee,
+            crypto_out_with_fee: tx.crypto_out_with_fee,
+            fiat_out_no_fee: tx.fiat_out_no_fee,
+            fiat_fee: tx.fiat_fee,
+            unique_id: &tx.unique_id,
+            notes: tx.notes.as_deref(),
+        }
+    }
+}
+
+/// Owned [`IntraData`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntraTransaction {
+    #[serde(with = "crate::date")]
+    pub timestamp: DateTime<Utc>,
+    pub asset: String,
+    pub from_exchange: String,
+    pub from_holder: String,
+    pub to_exchange: String,
+    pub to_holder: String,
+    pub spot_price: Option<Amount>,
+    pub crypto_sent: Amount,
+    pub crypto_received: Amount,
+    pub unique_id: String,
+    pub notes: Option<String>,
+}
+
+impl<'a> From<&IntraData<'a>> for IntraTransaction {
+    fn from(data: &IntraData<'a>) -> Self {
+        Self {
+            timestamp: data.timestamp,
+            asset: data.asset.to_string(),
+            from_exchange: data.from_exchange.to_string(),
+            from_holder: data.from_holder.to_string(),
+            to_exchange: data.to_exchange.to_string(),
+            to_holder: data.to_holder.to_string(),
+            spot_price: data.spot_price,
+            crypto_sent: data.crypto_sent,
+            crypto_received: data.crypto_received,
+            unique_id: data.unique_id.to_string(),
+            notes: data.notes.map(str::to_string),
+        }
+    }
+}
+
+impl<'a> From<&'a IntraTransaction> for IntraData<'a> {
+    fn from(tx: &'a IntraTransaction) -> Self {
+        Self {
+            timestamp: tx.timestamp,
+            asset: &tx.asset,
+            from_exchange: &tx.from_exchange,
+            from_holder: &tx.from_holder,
+            to_exchange: &tx.to_exchange,
+            to_holder: &tx.to_holder,
+            spot_price: tx.spot_price,
+            crypto_sent: tx.crypto_sent,
+            crypto_received: tx.crypto_received,
+            unique_id: &tx.unique_id,
+            notes: tx.notes.as_deref(),
+        }
+    }
+}
+
+/// A row of any of the IN, OUT or INTRA tables.
+///
+/// A transaction is identified by its timestamp, table and unique id, IE: a conversion is an OUT
+/// and an IN transaction sharing the timestamp and id of the trade. Transactions are ordered by
+/// timestamp, with acquisitions before transfers before disposals of the same instant
+#[derive(Debug, Clone)]
+pub enum Transaction {
+    In(InTransaction),
+    Out(OutTransaction),
+    Intra(IntraTransaction),
+}
+
+impl Transaction {
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            Self::In(tx) => tx.timestamp,
+            Self::Out(tx) => tx.timestamp,
+            Self::Intra(tx) => tx.timestamp,
+        }
+    }
+
+    pub fn asset(&self) -> &str {
+        match self {
+            Self::In(tx) => &tx.asset,
+            Self::Out(tx) => &tx.asset,
+            Self::Intra(tx) => &tx.asset,
+        }
+    }
+
+    pub fn unique_id(&self) -> &str {
+        match self {
+            Self::In(tx) => &tx.unique_id,
+            Self::Out(tx) => &tx.unique_id,
+            Self::Intra(tx) => &tx.unique_id,
+        }
+    }
+
+    /// Order of the table within the same timestamp
+    fn table(&self) -> u8 {
+        match self {
+            Self::In(_) => 0,
+            Self::Intra(_) => 1,
+            Self::Out(_) => 2,
+        }
+    }
+
+    // Identity and order of a transaction
+    fn key(&self) -> (DateTime<Utc>, u8, &str) {
+        (self.timestamp(), self.table(), self.unique_id())
+    }
+}
+
+impl<'a> From<&InputData<'a>> for Transaction {
+    fn from(data: &InputData<'a>) -> Self {
+        Self::In(data.into())
+    }
+}
+
+impl<'a> From<InputData<'a>> for Transaction {
+    fn from(data: InputData<'a>) -> Self {
+        Self::In((&data).into())
+    }
+}
+
+impl<'a> From<&OutputData<'a>> for Transaction {
+    fn from(data: &OutputData<'a>) -> Self {
+        Self::Out(data.into())
+    }
+}
+
+impl<'a> From<OutputData<'a>> for Transaction {
+    fn from(data: OutputData<'a>) -> Self {
+        Self::Out((&data).into())
+    }
+}
+
+impl<'a> From<&IntraData<'a>> for Transaction {
+    fn from(data: &IntraData<'a>) -> Self {
+        Self::Intra(data.into())
+    }
+}
+
+impl<'a> From<IntraData<'a>> for Transaction {
+    fn from(data: IntraData<'a>) -> Self {
+        Self::Intra((&data).into())
+    }
+}
+
+impl<'a> TryFrom<&'a Transaction> for InputData<'a> {
+    type Error = &'a Transaction;
+    fn try_from(tx: &'a Transaction) -> Result<Self, Self::Error> {
+        match tx {
+            Transaction::In(tx) => Ok(tx.into()),
+            _ => Err(tx),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Transaction> for OutputData<'a> {
+    type Error = &'a Transaction;
+    fn try_from(tx: &'a Transaction) -> Result<Self, Self::Error> {
+        match tx {
+            Transaction::Out(tx) => Ok(tx.into()),
+            _ => Err(tx),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Transaction> for IntraData<'a> {
+    type Error = &'a Transaction;
+    fn try_from(tx: &'a Transaction) -> Result<Self, Self::Error> {
+        match tx {
+            Transaction::Intra(tx) => Ok(tx.into()),
+            _ => Err(tx),
+        }
+    }
+}
+
+impl PartialEq for Transaction {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Transaction {}
+
+impl Hash for Transaction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for Transaction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Transaction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Transaction;
+    use crate::{
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        Amount,
+    };
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::{cmp::Ordering, collections::HashSet};
+
+    fn date(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, 1, day, 0, 0, 0).unwrap()
+    }
+
+    fn buy(unique_id: &str, timestamp: DateTime<Utc>) -> InputData<'_> {
+        InputData {
+            timestamp,
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: Amount::from(100),
+            crypto_in: Amount::from(1),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: None,
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: Some("order"),
+        }
+    }
+
+    fn sell(unique_id: &str, timestamp: DateTime<Utc>) -> OutputData<'_> {
+        OutputData {
+            timestamp,
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: Amount::from(100),
+            crypto_out_no_fee: Amount::from(1),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: None,
+            fiat_fee: None,
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn transfer(unique_id: &str, timestamp: DateTime<Utc>) -> IntraData<'_> {
+        IntraData {
+            timestamp,
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Coinbase",
+            to_holder: "Bob",
+            spot_price: None,
+            crypto_sent: Amount::from(1),
+            crypto_received: Amount::from(1),
+            unique_id,
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn should_convert_transactions() {
+        let tx = Transaction::from(buy("b1", date(1)));
+        assert_eq!(Ok(buy("b1", date(1))), InputData::try_from(&tx));
+        assert!(OutputData::try_from(&tx).is_err());
+        let tx = Transaction::from(sell("s1", date(1)));
+        assert_eq!(Ok(sell("s1", date(1))), OutputData::try_from(&tx));
+        let tx = Transaction::from(transfer("t1", date(1)));
+        assert_eq!(Ok(transfer("t1", date(1))), IntraData::try_from(&tx));
+        assert!(InputData::try_from(&tx).is_err());
+
+        // Outlives the record it was read from
+        let tx = {
+            let unique_id = String::from("b2");
+            Transaction::from(buy(&unique_id, date(2)))
+        };
+        assert_eq!("b2", tx.unique_id());
+        assert_eq!("BTC", tx.asset());
+    }
+
+    #[test]
+    fn should_sort_and_dedup_transactions() {
+        let mut txs = vec![
+            Transaction::from(sell("c1", date(2))),
+            Transaction::from(buy("c1", date(2))),
+            Transaction::from(transfer("t1", date(2))),
+            Transaction::from(buy("b1", date(1))),
+        ];
+        txs.sort();
+        let order: Vec<_> = txs.iter().map(|tx| tx.unique_id()).collect();
+        assert_eq!(vec!["b1", "c1", "t1", "c1"], order);
+
+        // The same trade imported twice
+        let set: HashSet<_> = txs
+            .into_iter()
+            .chain([Transaction::from(buy("b1", date(1)))])
+            .collect();
+        assert_eq!(4, set.len());
+
+        // Equal exactly when ordered as equal
+        let a = Transaction::from(buy("b1", date(1)));
+        let b = Transaction::from(buy("b1", date(3)));
+        assert_ne!(a, b);
+        assert_eq!(Ordering::Less, a.cmp(&b));
+        assert_eq!(Ordering::Equal, a.cmp(&a.clone()));
+    }
+}
@@ -0,0 +1,571 @@
+/// uk.rs
+///
+/// UK share identification of crypto disposals: same day acquisitions first, then acquisitions
+/// of the next 30 days, then the Section 104 pool at average cost. Each holder has their own
+/// pools
+/// https://www.gov.uk/hmrc-internal-manuals/cryptoassets-manual/crypto22200
+use crate::{Amount, Transaction};
+use chrono::{Datelike, Days, NaiveDate};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    #[error("{asset} disposal of {holder} on {date} exceeds the pool by {missing}")]
+    Insufficient {
+        holder: String,
+        asset: String,
+        date: NaiveDate,
+        missing: Amount,
+    },
+    #[error("{asset} transfer {unique_id} has no spot price to value {amount}")]
+    Unpriced {
+        asset: String,
+        unique_id: String,
+        amount: Amount,
+    },
+}
+
+/// Tax year starting on 6 April of the returned year
+pub fn tax_year(date: NaiveDate) -> i32 {
+    match (date.month(), date.day()) {
+        (1..=3, _) | (4, 1..=5) => date.year() - 1,
+        _ => date.year(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Rule {
+    SameDay,
+    /// Acquired within 30 days after the disposal
+    BedAndBreakfast,
+    Section104,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SameDay => f.write_str("same day"),
+            Self::BedAndBreakfast => f.write_str("bed and breakfast"),
+            Self::Section104 => f.write_str("section 104"),
+        }
+    }
+}
+
+/// Part of a disposal matched by a rule, with the date of the acquisition it was matched with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub rule: Rule,
+    pub acquired: Option<NaiveDate>,
+    pub amount: Amount,
+    pub cost: Amount,
+}
+
+/// Disposals of an asset by a holder on the same day, treated as a single disposal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposal {
+    pub date: NaiveDate,
+    pub holder: String,
+    pub asset: String,
+    pub amount: Amount,
+    pub proceeds: Amount,
+    pub matches: Vec<Match>,
+}
+
+impl Disposal {
+    pub fn tax_year(&self) -> i32 {
+        tax_year(self.date)
+    }
+
+    pub fn cost(&self) -> Amount {
+        self.matches.iter().map(|m| m.cost).sum()
+    }
+
+    pub fn gain(&self) -> Amount {
+        self.proceeds - self.cost()
+    }
+
+    /// Amount matched by a rule
+    pub fn matched(&self, rule: Rule) -> Amount {
+        self.matches
+            .iter()
+            .filter(|m| m.rule == rule)
+            .map(|m| m.amount)
+            .sum()
+    }
+}
+
+/// Section 104 pool of an asset of a holder at the end of a tax year
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pool {
+    pub tax_year: i32,
+    pub holder: String,
+    pub asset: String,
+    pub amount: Amount,
+    pub cost: Amount,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Identification {
+    /// Sorted by date, holder and asset
+    pub disposals: Vec<Disposal>,
+    /// Sorted by tax year, holder and asset
+    pub pools: Vec<Pool>,
+}
+
+impl Identification {
+    /// Amount matched by each rule, per asset, for the disposals of a holder in a tax year
+    pub fn matched(&self, holder: &str, tax_year: i32) -> BTreeMap<(&str, Rule), Amount> {
+        let mut matched = BTreeMap::new();
+        let disposals = self.disposals.iter();
+        for disposal in disposals.filter(|d| d.holder == holder && d.tax_year() == tax_year) {
+            for m in disposal.matches.iter() {
+                *matched
+                    .entry((disposal.asset.as_str(), m.rule))
+                    .or_insert(Amount::ZERO) += m.amount;
+            }
+        }
+        matched
+    }
+}
+
+/// Acquisitions of an asset on the same day
+#[derive(Debug, Default)]
+struct Acquisition {
+    amount: Amount,
+    cost: Amount,
+    remaining: Amount,
+}
+
+impl Acquisition {
+    /// Cost of part of the acquisition
+    fn cost_of(&self, amount: Amount) -> Amount {
+        match amount == self.amount {
+            true => self.cost,
+            false => (self.cost * amount)
+                .checked_div(self.amount)
+                .unwrap_or(Amount::ZERO),
+        }
+    }
+}
+
+/// Holder and asset of a pool
+type Key<'a> = (&'a str, &'a str);
+
+#[derive(Debug, Default)]
+struct Asset {
+    acquisitions: BTreeMap<NaiveDate, Acquisition>,
+    disposals: BTreeMap<NaiveDate, Disposal>,
+}
+
+impl Asset {
+    fn acquire(&mut self, date: NaiveDate, amount: Amount, cost: Amount) {
+        let acquisition = self.acquisitions.entry(date).or_default();
+        acquisition.amount += amount;
+        acquisition.remaining += amount;
+        acquisition.cost += cost;
+    }
+
+    fn dispose(&mut self, key: Key, date: NaiveDate, amount: Amount, proceeds: Amount) {
+        let disposal = self.disposals.entry(date).or_insert_with(|| Disposal {
+            date,
+            holder: key.0.to_string(),
+            asset: key.1.to_string(),
+            amount: Amount::ZERO,
+            proceeds: Amount::ZERO,
+            matches: Vec::new(),
+        });
+        disposal.amount += amount;
+        disposal.proceeds += proceeds;
+    }
+
+    /// Match every disposal with the acquisitions of the same day, then what remains with the
+    /// acquisitions of the next 30 days, earliest disposal and acquisition first
+    fn match_acquisitions(&mut self) {
+        for (date, disposal) in self.disposals.iter_mut() {
+            if let Some(acquisition) = self.acquisitions.get_mut(date) {
+                Self::match_acquisition(disposal, Rule::SameDay, *date, acquisition);
+            }
+        }
+        for (date, disposal) in self.disposals.iter_mut() {
+            let (start, end) = (*date + Days::new(1), *date + Days::new(30));
+            for (acquired, acquisition) in self.acquisitions.range_mut(start..=end) {
+                Self::match_acquisition(disposal, Rule::BedAndBreakfast, *acquired, acquisition);
+            }
+        }
+    }
+
+    fn match_acquisition(
+        disposal: &mut Disposal,
+        rule: Rule,
+        acquired: NaiveDate,
+        acquisition: &mut Acquisition,
+    ) {
+        let matched: Amount = disposal.matches.iter().map(|m| m.amount).sum();
+        let amount = (disposal.amount - matched).min(acquisition.remaining);
+        if amount <= Amount::ZERO {
+            return;
+        }
+        acquisition.remaining -= amount;
+        disposal.matches.push(Match {
+            rule,
+            acquired: Some(acquired),
+            amount,
+            cost: acquisition.cost_of(amount),
+        });
+    }
+
+    /// Pool what remains of the acquisitions, and match what remains of the disposals with the
+    /// pool, recording the pool at the end of each tax year
+    fn match_pool(mut self, key: Key, pools: &mut Vec<Pool>) -> Result<Vec<Disposal>, Error> {
+        let (holder, asset) = key;
+        let mut dates: Vec<_> = self
+            .acquisitions
+            .keys()
+            .chain(self.disposals.keys())
+            .copied()
+            .collect();
+        dates.sort();
+        dates.dedup();
+        let (mut amount, mut cost) = (Amount::ZERO, Amount::ZERO);
+        let mut year = dates.first().map(|date| tax_year(*date));
+        for date in dates {
+            while let Some(end) = year.filter(|y| *y < tax_year(date)) {
+                pools.push(Pool {
+                    tax_year: end,
+                    holder: holder.to_string(),
+                    asset: asset.to_string(),
+                    amount,
+                    cost,
+                });
+                year = Some(end + 1);
+            }
+            if let Some(acquisition) = self.acquisitions.get(&date) {
+                amount += acquisition.remaining;
+                cost += acquisition.cost_of(acquisition.remaining);
+            }
+            if let Some(disposal) = self.disposals.get_mut(&date) {
+                let matched: Amount = disposal.matches.iter().map(|m| m.amount).sum();
+                let unmatched = disposal.amount - matched;
+                if unmatched <= Amount::ZERO {
+                    continue;
+                }
+                if unmatched > amount {
+                    return Err(Error::Insufficient {
+                        holder: holder.to_string(),
+                        asset: asset.to_string(),
+                        date,
+                        missing: unmatched - amount,
+                    });
+                }
+                let pooled = match unmatched == amount {
+                    true => cost,
+                    false => (cost * unmatched)
+                        .checked_div(amount)
+                        .unwrap_or(Amount::ZERO),
+                };
+                amount -= unmatched;
+                cost -= pooled;
+                disposal.matches.push(Match {
+                    rule: Rule::Section104,
+                    acquired: None,
+                    amount: unmatched,
+                    cost: pooled,
+                });
+            }
+        }
+        if let Some(tax_year) = year {
+            pools.push(Pool {
+                tax_year,
+                holder: holder.to_string(),
+                asset: asset.to_string(),
+                amount,
+                cost,
+            });
+        }
+        Ok(std::mem::take(&mut self.disposals).into_values().collect())
+    }
+}
+
+/// Identify the acquisitions matched by every disposal. Dates are UTC days.
+///
+/// The cost of an acquisition includes its fiat fee. A crypto fee of a disposal is disposed of
+/// along with it, and the fee of a transfer between wallets is disposed of at its spot price. A
+/// transfer to another holder is a gift, disposed of by the sender and acquired by the receiver
+/// at its spot price. A transfer which is valued needs a spot price
+pub fn compute(transactions: &[Transaction]) -> Result<Identification, Error> {
+    let mut assets: BTreeMap<Key, Asset> = BTreeMap::new();
+    for tx in transactions.iter() {
+        let date = tx.timestamp().date_naive();
+        match tx {
+            Transaction::In(tx) => {
+                let cost = match tx.fiat_in_with_fee {
+                    Some(cost) => cost,
+                    None => tx.crypto_in * tx.spot_price + tx.fiat_fee,
+                };
+                let key = (tx.holder.as_str(), tx.asset.as_str());
+                assets
+                    .entry(key)
+                    .or_default()
+                    .acquire(date, tx.crypto_in, cost);
+            }
+            Transaction::Out(tx) => {
+                let proceeds = tx
+                    .fiat_out_no_fee
+                    .unwrap_or(tx.crypto_out_no_fee * tx.spot_price);
+                let amount = tx.crypto_out_no_fee + tx.crypto_fee;
+                let key = (tx.holder.as_str(), tx.asset.as_str());
+                assets
+                    .entry(key)
+                    .or_default()
+                    .dispose(key, date, amount, proceeds);
+            }
+            Transaction::Intra(tx) => {
+                let value = |amount| match tx.spot_price {
+                    Some(price) => Ok(amount * price),
+                    None => Err(Error::Unpriced {
+                        asset: tx.asset.clone(),
+                        unique_id: tx.unique_id.clone(),
+                        amount,
+                    }),
+                };
+                let from = (tx.from_holder.as_str(), tx.asset.as_str());
+                let sent = match tx.from_holder == tx.to_holder {
+                    true => tx.crypto_sent - tx.crypto_received,
+                    false => tx.crypto_sent,
+                };
+                if sent > Amount::ZERO {
+                    assets
+                        .entry(from)
+                        .or_default()
+                        .dispose(from, date, sent, value(sent)?);
+                }
+                if tx.from_holder != tx.to_holder {
+                    let received = tx.crypto_received;
+                    let to = (tx.to_holder.as_str(), tx.asset.as_str());
+                    assets
+                        .entry(to)
+                        .or_default()
+                        .acquire(date, received, value(received)?);
+                }
+            }
+        }
+    }
+
+    let mut identification = Identification::default();
+    for (key, mut asset) in assets.into_iter() {
+        asset.match_acquisitions();
+        let disposals = asset.match_pool(key, &mut identification.pools)?;
+        identification.disposals.extend(disposals);
+    }
+    identification
+        .disposals
+        .sort_by(|a, b| (a.date, &a.holder, &a.asset).cmp(&(b.date, &b.holder, &b.asset)));
+    identification
+        .pools
+        .sort_by(|a, b| (a.tax_year, &a.holder, &a.asset).cmp(&(b.tax_year, &b.holder, &b.asset)));
+    Ok(identification)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compute, tax_year, Error, Rule};
+    use crate::{
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        Amount, Transaction,
+    };
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn buy(unique_id: &str, date: NaiveDate, vol: &str, cost: &str) -> Transaction {
+        Transaction::from(InputData {
+            timestamp: Utc.from_utc_datetime(&date.and_hms_opt(10, 0, 0).unwrap()),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: Amount::ZERO,
+            crypto_in: amount(vol),
+            crypto_fee: None,
+            fiat_in_no_fee: None,
+            fiat_in_with_fee: Some(amount(cost)),
+            fiat_fee: Amount::ZERO,
+            unique_id,
+            notes: None,
+        })
+    }
+
+    fn sell(unique_id: &str, date: NaiveDate, vol: &str, proceeds: &str) -> Transaction {
+        Transaction::from(OutputData {
+            timestamp: Utc.from_utc_datetime(&date.and_hms_opt(12, 0, 0).unwrap()),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: Amount::ZERO,
+            crypto_out_no_fee: amount(vol),
+            crypto_fee: Amount::ZERO,
+            crypto_out_with_fee: None,
+            fiat_out_no_fee: Some(amount(proceeds)),
+            fiat_fee: None,
+            unique_id,
+            notes: None,
+        })
+    }
+
+    #[test]
+    fn should_find_tax_year() {
+        assert_eq!(2022, tax_year(day(2023, 4, 5)));
+        assert_eq!(2023, tax_year(day(2023, 4, 6)));
+        assert_eq!(2022, tax_year(day(2022, 12, 31)));
+        assert_eq!(2021, tax_year(day(2022, 1, 1)));
+    }
+
+    #[test]
+    fn should_match_by_rule() {
+        // HMRC example: 100 tokens pooled for £1000, then 50 more for £1250
+        let transactions = [
+            buy("b1", day(2022, 1, 1), "100", "1000"),
+            buy("b2", day(2022, 3, 1), "50", "1250"),
+            buy("b3", day(2022, 6, 1), "10", "400"),
+            sell("s1", day(2022, 6, 1), "30", "1500"),
+            buy("b4", day(2022, 6, 20), "5", "300"),
+            buy("b5", day(2022, 7, 15), "100", "9000"),
+        ];
+        let identification = compute(&transactions).unwrap();
+        assert_eq!(1, identification.disposals.len());
+        let disposal = &identification.disposals[0];
+        assert_eq!(amount("10"), disposal.matched(Rule::SameDay));
+        assert_eq!(amount("5"), disposal.matched(Rule::BedAndBreakfast));
+        assert_eq!(amount("15"), disposal.matched(Rule::Section104));
+        // 400 + 300 + 15 * 2250 / 150
+        assert_eq!(amount("925"), disposal.cost());
+        assert_eq!(amount("575"), disposal.gain());
+        assert_eq!(
+            Some(&amount("15")),
+            identification
+                .matched("Bob", 2022)
+                .get(&("BTC", Rule::Section104))
+        );
+
+        // Pool at the end of 2021/22 and 2022/23
+        let pools: Vec<_> = identification
+            .pools
+            .iter()
+            .map(|pool| (pool.tax_year, pool.amount, pool.cost))
+            .collect();
+        assert_eq!(
+            vec![
+                (2021, amount("150"), amount("2250")),
+                (2022, amount("235"), amount("11025")),
+            ],
+            pools
+        );
+    }
+
+    #[test]
+    fn should_not_dispose_more_than_pooled() {
+        let transactions = [
+            buy("b1", day(2022, 1, 1), "1", "100"),
+            sell("s1", day(2022, 2, 1), "2", "500"),
+        ];
+        assert_eq!(
+            Err(Error::Insufficient {
+                holder: "Bob".into(),
+                asset: "BTC".into(),
+                date: day(2022, 2, 1),
+                missing: amount("1")
+            }),
+            compute(&transactions)
+        );
+    }
+
+    #[test]
+    fn should_match_same_day_before_bed_and_breakfast() {
+        let transactions = [
+            buy("b1", day(2022, 1, 1), "10", "100"),
+            sell("s1", day(2022, 2, 1), "10", "200"),
+            buy("b2", day(2022, 2, 10), "10", "300"),
+            sell("s2", day(2022, 2, 10), "10", "400"),
+        ];
+        let identification = compute(&transactions).unwrap();
+        let matches: Vec<_> = identification
+            .disposals
+            .iter()
+            .map(|d| (d.date, d.matches[0].rule, d.cost()))
+            .collect();
+        assert_eq!(
+            vec![
+                (day(2022, 2, 1), Rule::Section104, amount("100")),
+                (day(2022, 2, 10), Rule::SameDay, amount("300")),
+            ],
+            matches
+        );
+    }
+
+    #[test]
+    fn should_pool_per_holder() {
+        let gift = |spot_price: Option<&str>| {
+            Transaction::from(IntraData {
+                timestamp: Utc.from_utc_datetime(&day(2022, 3, 1).and_hms_opt(10, 0, 0).unwrap()),
+                asset: "BTC",
+                from_exchange: "Kraken",
+                from_holder: "Bob",
+                to_exchange: "Ledger",
+                to_holder: "Alice",
+                spot_price: spot_price.map(amount),
+                crypto_sent: amount("4"),
+                crypto_received: amount("4"),
+                unique_id: "g1",
+                notes: None,
+            })
+        };
+        let unpriced = [buy("b1", day(2022, 1, 1), "10", "100"), gift(None)];
+        assert_eq!(
+            Err(Error::Unpriced {
+                asset: "BTC".into(),
+                unique_id: "g1".into(),
+                amount: amount("4")
+            }),
+            compute(&unpriced)
+        );
+
+        let transactions = [
+            buy("b1", day(2022, 1, 1), "10", "100"),
+            gift(Some("50")),
+            sell("s1", day(2022, 6, 1), "6", "600"),
+        ];
+        let identification = compute(&transactions).unwrap();
+        let disposals: Vec<_> = identification
+            .disposals
+            .iter()
+            .map(|d| (d.holder.as_str(), d.amount, d.proceeds, d.cost()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Bob", amount("4"), amount("200"), amount("40")),
+                ("Bob", amount("6"), amount("600"), amount("60")),
+            ],
+            disposals
+        );
+        let pools: Vec<_> = identification
+            .pools
+            .iter()
+            .map(|pool| (pool.tax_year, pool.holder.as_str(), pool.amount, pool.cost))
+            .collect();
+        assert_eq!(
+            vec![
+                (2021, "Alice", amount("4"), amount("200")),
+                (2021, "Bob", amount("6"), amount("60")),
+                (2022, "Bob", amount("0"), amount("0")),
+            ],
+            pools
+        );
+    }
+}
@@ -0,0 +1,420 @@
+/// validate.rs
+///
+/// Check rows against the rules rp2 enforces on its input tables, before handing files to rp2
+/// https://github.com/eprbell/rp2/blob/main/docs/input_files.md
+use crate::{
+    sheet::{InputData, IntraData, OutputData},
+    Amount,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Table {
+    In,
+    Out,
+    Intra,
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::In => f.write_str("IN"),
+            Self::Out => f.write_str("OUT"),
+            Self::Intra => f.write_str("INTRA"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// Amount below zero
+    Negative,
+    /// Amount of crypto moved must be above zero
+    NotPositive,
+    /// Amount differs from the one derived from the other fields of the row
+    Mismatch,
+    /// Intra row receiving more than it sends
+    Received,
+    /// Crypto and fiat fee given on the same IN row
+    BothFees,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Negative => f.write_str("negative"),
+            Self::NotPositive => f.write_str("not positive"),
+            Self::Mismatch => f.write_str("mismatch"),
+            Self::Received => f.write_str("received more than sent"),
+            Self::BothFees => f.write_str("both crypto and fiat fee"),
+        }
+    }
+}
+
+/// A broken rule, where `row` is the index of the row within its table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub table: Table,
+    pub row: usize,
+    pub unique_id: String,
+    pub field: &'static str,
+    pub rule: Rule,
+    pub expected: Amount,
+    pub actual: Amount,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} row {} ({}) {}: {}, expected {} found {}",
+            self.table, self.row, self.unique_id, self.field, self.rule, self.expected, self.actual
+        )
+    }
+}
+
+/// Largest difference accepted between an amount and the one derived from the other fields. The
+/// absolute tolerance grows with the derived amount, as a spot price rounded to the cent is off by
+/// more on a larger trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tolerance {
+    pub fiat: Amount,
+    pub crypto: Amount,
+    /// Fraction of the derived amount, when larger than the absolute tolerance
+    pub relative: Amount,
+}
+
+impl Default for Tolerance {
+    /// A cent, a satoshi, or one part per million
+    fn default() -> Self {
+        Self {
+            fiat: Amount::new(1, 2),
+            crypto: Amount::new(1, 8),
+            relative: Amount::new(1, 6),
+        }
+    }
+}
+
+impl Tolerance {
+    /// Difference accepted from an expected amount, given the absolute tolerance of its kind
+    pub fn of(&self, absolute: Amount, expected: Amount) -> Amount {
+        expected
+            .abs()
+            .checked_mul(self.relative)
+            .map_or(absolute, |relative| absolute.max(relative))
+    }
+}
+
+/// Findings of a single row
+struct Row<'a> {
+    table: Table,
+    row: usize,
+    unique_id: &'a str,
+    tolerance: &'a Tolerance,
+    findings: &'a mut Vec<Finding>,
+}
+
+impl<'a> Row<'a> {
+    fn push(&mut self, field: &'static str, rule: Rule, expected: Amount, actual: Amount) {
+        self.findings.push(Finding {
+            table: self.table,
+            row: self.row,
+            unique_id: self.unique_id.to_string(),
+            field,
+            rule,
+            expected,
+            actual,
+        });
+    }
+
+    fn non_negative(&mut self, field: &'static str, actual: Option<Amount>) {
+        if let Some(actual) = actual.filter(Amount::is_negative) {
+            self.push(field, Rule::Negative, Amount::ZERO, actual);
+        }
+    }
+
+    fn positive(&mut self, field: &'static str, actual: Amount) {
+        if actual <= Amount::ZERO {
+            self.push(field, Rule::NotPositive, Amount::ZERO, actual);
+        }
+    }
+
+    fn matches(
+        &mut self,
+        field: &'static str,
+        tolerance: Amount,
+        expected: Option<Amount>,
+        actual: Option<Amount>,
+    ) {
+        let (Some(expected), Some(actual)) = (expected, actual) else {
+            return;
+        };
+        let tolerance = self.tolerance.of(tolerance, expected);
+        let within = expected
+            .checked_sub(actual)
+            .is_some_and(|diff| diff.abs() <= tolerance);
+        if !within {
+            self.push(field, Rule::Mismatch, expected, actual);
+        }
+    }
+}
+
+pub fn validate_input(
+    row: usize,
+    data: &InputData<'_>,
+    tolerance: &Tolerance,
+    findings: &mut Vec<Finding>,
+) {
+    let mut row = Row {
+        table: Table::In,
+        row,
+        unique_id: data.unique_id,
+        tolerance,
+        findings,
+    };
+    row.positive("crypto_in", data.crypto_in);
+    row.non_negative("spot_price", Some(data.spot_price));
+    row.non_negative("crypto_fee", data.crypto_fee);
+    row.non_negative("fiat_in_no_fee", data.fiat_in_no_fee);
+    row.non_negative("fiat_in_with_fee", data.fiat_in_with_fee);
+    row.non_negative("fiat_fee", Some(data.fiat_fee));
+    let fiat = row.tolerance.fiat;
+    row.matches(
+        "fiat_in_no_fee",
+        fiat,
+        data.crypto_in.checked_mul(data.spot_price),
+        data.fiat_in_no_fee,
+    );
+    row.matches(
+        "fiat_in_with_fee",
+        fiat,
+        data.fiat_in_no_fee
+            .and_then(|no_fee| no_fee.checked_add(data.fiat_fee)),
+        data.fiat_in_with_fee,
+    );
+    if let Some(crypto_fee) = data.crypto_fee.filter(|fee| !fee.is_zero()) {
+        if !data.fiat_fee.is_zero() {
+            row.push("crypto_fee", Rule::BothFees, Amount::ZERO, crypto_fee);
+        }
+    }
+}
+
+pub fn validate_output(
+    row: usize,
+    data: &OutputData<'_>,
+    tolerance: &Tolerance,
+    findings: &mut Vec<Finding>,
+) {
+    let mut row = Row {
+        table: Table::Out,
+        row,
+        unique_id: data.unique_id,
+        tolerance,
+        findings,
+    };
+    row.non_negative("spot_price", Some(data.spot_price));
+    row.non_negative("crypto_out_no_fee", Some(data.crypto_out_no_fee));
+    row.non_negative("crypto_fee", Some(data.crypto_fee));
+    row.non_negative("crypto_out_with_fee", data.crypto_out_with_fee);
+    row.non_negative("fiat_out_no_fee", data.fiat_out_no_fee);
+    row.non_negative("fiat_fee", data.fiat_fee);
+    if let Some(total) = data.crypto_out_no_fee.checked_add(data.crypto_fee) {
+        row.positive("crypto_out_with_fee", total);
+    }
+    let (fiat, crypto) = (row.tolerance.fiat, row.tolerance.crypto);
+    row.matches(
+        "crypto_out_with_fee",
+        crypto,
+        data.crypto_out_no_fee.checked_add(data.crypto_fee),
+        data.crypto_out_with_fee,
+    );
+    row.matches(
+        "fiat_out_no_fee",
+        fiat,
+        data.crypto_out_no_fee.checked_mul(data.spot_price),
+        data.fiat_out_no_fee,
+    );
+    // A fee paid in fiat has no crypto fee to be derived from
+    if !data.crypto_fee.is_zero() {
+        row.matches(
+            "fiat_fee",
+            fiat,
+            data.crypto_fee.checked_mul(data.spot_price),
+            data.fiat_fee,
+        );
+    }
+}
+
+pub fn validate_intra(
+    row: usize,
+    data: &IntraData<'_>,
+    tolerance: &Tolerance,
+    findings: &mut Vec<Finding>,
+) {
+    let mut row = Row {
+        table: Table::Intra,
+        row,
+        unique_id: data.unique_id,
+        tolerance,
+        findings,
+    };
+    row.non_negative("spot_price", data.spot_price);
+    row.positive("crypto_sent", data.crypto_sent);
+    row.non_negative("crypto_received", Some(data.crypto_received));
+    if data.crypto_received > data.crypto_sent {
+        row.push(
+            "crypto_received",
+            Rule::Received,
+            data.crypto_sent,
+            data.crypto_received,
+        );
+    }
+}
+
+/// Every broken rule of the tables, in table and row order
+pub fn validate(
+    inputs: &[InputData<'_>],
+    outputs: &[OutputData<'_>],
+    intras: &[IntraData<'_>],
+    tolerance: &Tolerance,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (row, data) in inputs.iter().enumerate() {
+        validate_input(row, data, tolerance, &mut findings);
+    }
+    for (row, data) in outputs.iter().enumerate() {
+        validate_output(row, data, tolerance, &mut findings);
+    }
+    for (row, data) in intras.iter().enumerate() {
+        validate_intra(row, data, tolerance, &mut findings);
+    }
+    findings
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate, Finding, Rule, Table, Tolerance};
+    use crate::{
+        sheet::{Input, InputData, IntraData, Output, OutputData},
+        Amount,
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn amount(s: &str) -> Amount {
+        s.parse().unwrap()
+    }
+
+    fn buy<'a>(unique_id: &'a str, fiat_no_fee: &str, fiat_with_fee: &str) -> InputData<'a> {
+        InputData {
+            timestamp: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Input::Buy,
+            spot_price: amount("40000"),
+            crypto_in: amount("0.0123"),
+            crypto_fee: None,
+            fiat_in_no_fee: Some(amount(fiat_no_fee)),
+            fiat_in_with_fee: Some(amount(fiat_with_fee)),
+            fiat_fee: amount("1.5"),
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn sell<'a>(unique_id: &'a str, crypto_no_fee: &str, crypto_with_fee: &str) -> OutputData<'a> {
+        OutputData {
+            timestamp: Utc.with_ymd_and_hms(2022, 2, 1, 0, 0, 0).unwrap(),
+            asset: "BTC",
+            exchange: "Kraken",
+            holder: "Bob",
+            typ: Output::Sell,
+            spot_price: amount("50000"),
+            crypto_out_no_fee: amount(crypto_no_fee),
+            crypto_fee: amount("0.0001"),
+            crypto_out_with_fee: Some(amount(crypto_with_fee)),
+            fiat_out_no_fee: None,
+            fiat_fee: Some(amount("5")),
+            unique_id,
+            notes: None,
+        }
+    }
+
+    fn transfer<'a>(unique_id: &'a str, sent: &str, received: &str) -> IntraData<'a> {
+        IntraData {
+            timestamp: Utc.with_ymd_and_hms(2022, 3, 1, 0, 0, 0).unwrap(),
+            asset: "BTC",
+            from_exchange: "Kraken",
+            from_holder: "Bob",
+            to_exchange: "Coinbase",
+            to_holder: "Bob",
+            spot_price: None,
+            crypto_sent: amount(sent),
+            crypto_received: amount(received),
+            unique_id,
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn should_accept_valid_rows() {
+        let findings = validate(
+            &[buy("b1", "492", "493.5"), buy("b2", "492.004", "493.5")],
+            &[sell("s1", "0.01", "0.0101")],
+            &[transfer("t1", "0.001", "0.0009")],
+            &Tolerance::default(),
+        );
+        assert_eq!(Vec::<Finding>::new(), findings);
+    }
+
+    #[test]
+    fn should_scale_tolerance() {
+        let tolerance = Tolerance::default();
+        assert_eq!(amount("0.01"), tolerance.of(tolerance.fiat, amount("492")));
+        assert_eq!(
+            amount("4"),
+            tolerance.of(tolerance.fiat, amount("-4000000"))
+        );
+
+        // 100 BTC at a spot price rounded to the cent
+        let mut inputs = [buy("b1", "4000000.5", "4000002")];
+        inputs[0].crypto_in = amount("100");
+        let findings = validate(&inputs, &[], &[], &tolerance);
+        assert_eq!(Vec::<Finding>::new(), findings);
+        inputs[0].fiat_in_no_fee = Some(amount("4000005"));
+        inputs[0].fiat_in_with_fee = Some(amount("4000006.5"));
+        let findings = validate(&inputs, &[], &[], &tolerance);
+        assert_eq!(1, findings.len());
+        assert_eq!("fiat_in_no_fee", findings[0].field);
+    }
+
+    #[test]
+    fn should_find_broken_rules() {
+        let findings = validate(
+            &[buy("b1", "492", "494")],
+            &[sell("s1", "-0.01", "0.0101")],
+            &[transfer("t1", "0.001", "0.002")],
+            &Tolerance::default(),
+        );
+        let found: Vec<_> = findings
+            .iter()
+            .map(|f| (f.table, f.row, f.field, f.rule))
+            .collect();
+        assert_eq!(
+            vec![
+                (Table::In, 0, "fiat_in_with_fee", Rule::Mismatch),
+                (Table::Out, 0, "crypto_out_no_fee", Rule::Negative),
+                (Table::Out, 0, "crypto_out_with_fee", Rule::NotPositive),
+                (Table::Out, 0, "crypto_out_with_fee", Rule::Mismatch),
+                (Table::Intra, 0, "crypto_received", Rule::Received),
+            ],
+            found
+        );
+        assert_eq!(amount("493.5"), findings[0].expected);
+        assert_eq!(amount("494"), findings[0].actual);
+        assert_eq!(
+            "IN row 0 (b1) fiat_in_with_fee: mismatch, expected 493.5 found 494",
+            findings[0].to_string()
+        );
+    }
+}